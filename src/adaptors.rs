@@ -0,0 +1,49 @@
+//! Wrappers that change how a `Storable` value is laid out on disk
+//!
+//! Every adaptor wraps a value implementing `StorableBytes`, and is itself `Storable`
+//! with any writer and reader, so adaptors can be nested inside each other. `Zstd`,
+//! which needs its dictionary to be passed in, and `TypedBox`, which needs its
+//! `Registry`, are `StorableWithCtx` instead, `MsgPack` and `Serde` wrap serde types
+//! rather than `Storable` ones, and `Protobuf` wraps prost messages.
+#[cfg(feature = "base64")]
+mod base64;
+mod chunked;
+mod fallback;
+#[cfg(feature = "serde")]
+mod format;
+mod length_prefixed;
+#[cfg(feature = "prost")]
+mod protobuf;
+#[cfg(feature = "rmp-serde")]
+mod msgpack;
+mod records;
+mod registry;
+mod trailer;
+#[cfg(feature = "zstd")]
+mod zstd;
+
+#[cfg(feature = "base64")]
+pub use self::base64::Base64;
+pub use self::chunked::Chunked;
+pub use self::fallback::Fallback;
+#[cfg(feature = "bincode")]
+pub use self::format::BincodeFormat;
+#[cfg(feature = "json")]
+pub use self::format::JsonFormat;
+#[cfg(feature = "rmp-serde")]
+pub use self::format::MsgPackFormat;
+#[cfg(feature = "serde")]
+pub use self::format::{Format, Serde};
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+pub use self::format::SerdeFormat;
+pub use self::length_prefixed::LengthPrefixed;
+pub(crate) use self::length_prefixed::read_len_prefixed;
+#[cfg(feature = "rmp-serde")]
+pub use self::msgpack::MsgPack;
+#[cfg(feature = "prost")]
+pub use self::protobuf::Protobuf;
+pub use self::records::Records;
+pub use self::registry::{Registry, Tagged, TypedBox};
+pub use self::trailer::WithTrailer;
+#[cfg(feature = "zstd")]
+pub use self::zstd::{Zstd, ZstdDictionary};
@@ -0,0 +1,39 @@
+use std::io::{Read, Write};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::storable::*;
+
+/// Stores the wrapped value as base64 text
+///
+/// The bytes produced by the inner `Storable` are encoded with the standard base64
+/// alphabet, so binary formats end up in plain ASCII files. Whitespace around the
+/// encoded text is ignored on restore.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Base64<S>(pub S);
+
+impl<S, W, R> Storable<W, R> for Base64<S>
+where
+    S: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        let bytes = STANDARD
+            .decode(text.trim())
+            .map_err(|e| StorableRestoreError(format!("invalid base64: {}", e)))?;
+        from_bytes(&bytes).map(Base64)
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        let text = STANDARD.encode(to_bytes(&self.0)?);
+        writer
+            .write_all(text.as_bytes())
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
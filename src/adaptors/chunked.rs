@@ -0,0 +1,87 @@
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+use crate::storable::*;
+
+/// Size of the chunks `Chunked` splits values into.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Returns the CRC-32 (IEEE) checksum of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| {
+        (0..8).fold(crc ^ u32::from(b), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Stores the wrapped value in chunks, each with its own checksum, so that restoring
+/// a damaged file tells which part of it is damaged
+///
+/// The file starts with a table: the chunk size as a little-endian `u32`, the length of
+/// the value as a little-endian `u64`, then the CRC-32 checksum of each chunk, as a
+/// little-endian `u32`. The chunks follow, all of them of the chunk size except the
+/// last. Restoring checks every chunk against its checksum, and fails with the index of
+/// the first one that does not match, counting from 0.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Chunked<S>(pub S);
+
+impl<S, W, R> Storable<W, R> for Chunked<S>
+where
+    S: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        let truncated = || StorableRestoreError("chunk table is truncated".to_string());
+        if bytes.len() < 12 {
+            return Err(truncated());
+        }
+        let (chunk_size, rest) = bytes.split_at(4);
+        let (len, rest) = rest.split_at(8);
+        let chunk_size = u32::from_le_bytes(chunk_size.try_into().unwrap()) as usize;
+        let len = u64::from_le_bytes(len.try_into().unwrap());
+        if chunk_size == 0 {
+            return Err(StorableRestoreError("chunk size is 0".to_string()));
+        }
+
+        let chunks = len.div_ceil(chunk_size as u64);
+        let table_len = chunks.checked_mul(4).filter(|&table_len| table_len <= rest.len() as u64);
+        let (table, data) = rest.split_at(table_len.ok_or_else(truncated)? as usize);
+        if data.len() as u64 != len {
+            return Err(StorableRestoreError(format!(
+                "expected {} bytes of chunks but the file holds {}",
+                len,
+                data.len()
+            )));
+        }
+        for (index, (checksum, chunk)) in table.chunks(4).zip(data.chunks(chunk_size)).enumerate() {
+            if u32::from_le_bytes(checksum.try_into().unwrap()) != crc32(chunk) {
+                return Err(StorableRestoreError(format!("chunk {} is corrupt", index)));
+            }
+        }
+        from_bytes(data).map(Chunked)
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        let bytes = to_bytes(&self.0)?;
+        let mut table = Vec::with_capacity(12 + bytes.len().div_ceil(CHUNK_SIZE) * 4);
+        table.extend_from_slice(&(CHUNK_SIZE as u32).to_le_bytes());
+        table.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        for chunk in bytes.chunks(CHUNK_SIZE) {
+            table.extend_from_slice(&crc32(chunk).to_le_bytes());
+        }
+        writer
+            .write_all(&table)
+            .and_then(|()| writer.write_all(&bytes))
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
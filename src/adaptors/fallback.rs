@@ -0,0 +1,55 @@
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use crate::storable::*;
+
+/// Restores values written either in a new or in a legacy format
+///
+/// On restore, the file is read once and parsed as `New`. If that fails, it is parsed as
+/// `Old` instead, and converted to `New`. Values are always stored as `New`, so
+/// storing a restored value upgrades its file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fallback<New, Old>(pub New, PhantomData<Old>);
+
+impl<New, Old> Fallback<New, Old> {
+    /// Wraps `value`.
+    pub fn new(value: New) -> Fallback<New, Old> {
+        Fallback(value, PhantomData)
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> New {
+        self.0
+    }
+}
+
+impl<New, Old, W, R> Storable<W, R> for Fallback<New, Old>
+where
+    New: StorableBytes + From<Old>,
+    Old: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        match from_bytes::<New>(&bytes) {
+            Ok(value) => Ok(Fallback::new(value)),
+            Err(new_error) => match from_bytes::<Old>(&bytes) {
+                Ok(value) => Ok(Fallback::new(value.into())),
+                Err(old_error) => Err(StorableRestoreError(format!(
+                    "neither format matches: {}; legacy format: {}",
+                    new_error, old_error
+                ))),
+            },
+        }
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        writer
+            .write_all(&to_bytes(&self.0)?)
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::storable::*;
+
+/// A serde data format that values can be written in
+///
+/// Implementing `Format` for a new format makes `Serde` work with it.
+pub trait Format {
+    /// Writes `value` to `writer` in this format.
+    fn serialize_into<T: Serialize, W: Write>(value: &T, writer: W) -> Result<(), StorableStoreError>;
+
+    /// Reads a value of type `T` in this format from `reader`.
+    fn deserialize_from<T: DeserializeOwned, R: Read>(reader: R) -> Result<T, StorableRestoreError>;
+}
+
+/// JSON, through `serde_json`
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct JsonFormat;
+
+#[cfg(feature = "json")]
+impl Format for JsonFormat {
+    fn serialize_into<T: Serialize, W: Write>(value: &T, writer: W) -> Result<(), StorableStoreError> {
+        serde_json::to_writer(writer, value).map_err(|e| StorableStoreError(e.to_string()))
+    }
+
+    fn deserialize_from<T: DeserializeOwned, R: Read>(reader: R) -> Result<T, StorableRestoreError> {
+        serde_json::from_reader(reader).map_err(|e| StorableRestoreError(format!("invalid JSON: {}", e)))
+    }
+}
+
+/// Bincode, through `bincode` with its standard configuration
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct BincodeFormat;
+
+#[cfg(feature = "bincode")]
+impl Format for BincodeFormat {
+    fn serialize_into<T: Serialize, W: Write>(value: &T, mut writer: W) -> Result<(), StorableStoreError> {
+        bincode::serde::encode_into_std_write(value, &mut writer, bincode::config::standard())
+            .map(|_| ())
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+
+    fn deserialize_from<T: DeserializeOwned, R: Read>(mut reader: R) -> Result<T, StorableRestoreError> {
+        bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())
+            .map_err(|e| StorableRestoreError(format!("invalid bincode: {}", e)))
+    }
+}
+
+/// MessagePack, through `rmp_serde`, laid out like `MsgPack`
+#[cfg(feature = "rmp-serde")]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct MsgPackFormat;
+
+#[cfg(feature = "rmp-serde")]
+impl Format for MsgPackFormat {
+    fn serialize_into<T: Serialize, W: Write>(value: &T, mut writer: W) -> Result<(), StorableStoreError> {
+        rmp_serde::encode::write(&mut writer, value).map_err(|e| StorableStoreError(e.to_string()))
+    }
+
+    fn deserialize_from<T: DeserializeOwned, R: Read>(reader: R) -> Result<T, StorableRestoreError> {
+        rmp_serde::decode::from_read(reader).map_err(|e| StorableRestoreError(format!("invalid MessagePack: {}", e)))
+    }
+}
+
+/// Stores the wrapped value in format `F`, through its serde implementations
+///
+/// The format is part of the type, so moving a storage to another format only changes
+/// `F`, not the type of the values.
+pub struct Serde<T, F>(pub T, PhantomData<F>);
+
+impl<T, F> Serde<T, F> {
+    /// Wraps `value`.
+    pub fn new(value: T) -> Serde<T, F> {
+        Serde(value, PhantomData)
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+// Implemented by hand, so that they do not require `F` to implement the traits too.
+impl<T: fmt::Debug, F> fmt::Debug for Serde<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Serde").field(&self.0).finish()
+    }
+}
+
+impl<T: Clone, F> Clone for Serde<T, F> {
+    fn clone(&self) -> Self {
+        Serde::new(self.0.clone())
+    }
+}
+
+impl<T: PartialEq, F> PartialEq for Serde<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: Eq, F> Eq for Serde<T, F> {}
+
+impl<T, F> Deref for Serde<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, F> DerefMut for Serde<T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, F, W, R> Storable<W, R> for Serde<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+    W: Write,
+    R: Read,
+{
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        F::deserialize_from(reader).map(Serde::new)
+    }
+
+    fn store(&self, writer: W) -> Result<(), StorableStoreError> {
+        F::serialize_into(&self.0, writer)
+    }
+}
+
+/// A serde data format chosen at runtime, rather than in the type like with `Serde`
+///
+/// Any serde type is `StorableWithCtx` with a `SerdeFormat` as its context, so a
+/// `DirStorage` of plain serde values is stored with `DirStorage::store_with_ctx` and
+/// restored with `DirStorage::restore_with_ctx`, in the format given then. With
+/// `Options::serde_format`, the format is instead chosen once for the storage, and
+/// consulted by `DirStorage::store_formatted` and `DirStorage::restore_formatted`,
+/// though not by the methods that go through `Storable`, such as `DirStorage::store`.
+/// Restoring fails if a file holds anything after its value, and if a file would be
+/// restored in another format instead, the error names that format.
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SerdeFormat {
+    /// JSON, like `JsonFormat`.
+    #[cfg(feature = "json")]
+    Json,
+    /// Bincode, like `BincodeFormat`.
+    #[cfg(feature = "bincode")]
+    Bincode,
+    /// MessagePack, like `MsgPackFormat`.
+    #[cfg(feature = "rmp-serde")]
+    MsgPack,
+}
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+impl fmt::Display for SerdeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "json")]
+            SerdeFormat::Json => f.write_str("JSON"),
+            #[cfg(feature = "bincode")]
+            SerdeFormat::Bincode => f.write_str("bincode"),
+            #[cfg(feature = "rmp-serde")]
+            SerdeFormat::MsgPack => f.write_str("MessagePack"),
+        }
+    }
+}
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+impl SerdeFormat {
+    /// Formats tried, in this order, to tell what a file is written in, from the least
+    /// to the most likely to accept bytes that are not meant for it.
+    const PROBES: &'static [SerdeFormat] = &[
+        #[cfg(feature = "json")]
+        SerdeFormat::Json,
+        #[cfg(feature = "rmp-serde")]
+        SerdeFormat::MsgPack,
+        #[cfg(feature = "bincode")]
+        SerdeFormat::Bincode,
+    ];
+
+    /// Writes `value` to `writer` in this format.
+    pub fn serialize_into<T: Serialize, W: Write>(&self, value: &T, writer: W) -> Result<(), StorableStoreError> {
+        match *self {
+            #[cfg(feature = "json")]
+            SerdeFormat::Json => JsonFormat::serialize_into(value, writer),
+            #[cfg(feature = "bincode")]
+            SerdeFormat::Bincode => BincodeFormat::serialize_into(value, writer),
+            #[cfg(feature = "rmp-serde")]
+            SerdeFormat::MsgPack => MsgPackFormat::serialize_into(value, writer),
+        }
+    }
+
+    /// Reads a value of type `T` in this format from `reader`.
+    pub fn deserialize_from<T: DeserializeOwned, R: Read>(&self, reader: R) -> Result<T, StorableRestoreError> {
+        match *self {
+            #[cfg(feature = "json")]
+            SerdeFormat::Json => JsonFormat::deserialize_from(reader),
+            #[cfg(feature = "bincode")]
+            SerdeFormat::Bincode => BincodeFormat::deserialize_from(reader),
+            #[cfg(feature = "rmp-serde")]
+            SerdeFormat::MsgPack => MsgPackFormat::deserialize_from(reader),
+        }
+    }
+
+    /// Returns whether `bytes` hold exactly one value of type `T` in this format.
+    fn recognizes<T: DeserializeOwned>(&self, bytes: &[u8]) -> bool {
+        let mut unread = bytes;
+        self.deserialize_from::<T, _>(&mut unread).is_ok() && unread.is_empty()
+    }
+}
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+impl<T, W, R> StorableWithCtx<SerdeFormat, W, R> for T
+where
+    T: Serialize + DeserializeOwned,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R, format: &SerdeFormat) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        let mut unread = bytes.as_slice();
+        let result = format.deserialize_from(&mut unread).and_then(|value| match unread.len() {
+            0 => Ok(value),
+            n => Err(StorableRestoreError(format!("{} bytes left after the {} value", n, format))),
+        });
+        result.map_err(|e| {
+            match SerdeFormat::PROBES
+                .iter()
+                .find(|other| *other != format && other.recognizes::<T>(&bytes))
+            {
+                Some(other) => StorableRestoreError(format!("{}, but the file looks like {}", e, other)),
+                None => e,
+            }
+        })
+    }
+
+    fn store(&self, writer: W, format: &SerdeFormat) -> Result<(), StorableStoreError> {
+        format.serialize_into(self, writer)
+    }
+}
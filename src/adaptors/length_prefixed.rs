@@ -0,0 +1,91 @@
+use std::io::{self, Read, Write};
+
+use crate::storable::*;
+
+/// Reads the bytes following a length, as a little-endian `u64`, as many as it says.
+///
+/// Returns `None` if `reader` is at its end before the length. If it ends within the
+/// length or the bytes, the error is of kind `io::ErrorKind::UnexpectedEof`. This is
+/// the layout of the records of `Records`, of a stream written by `DirStorage::export`
+/// and of the frames of a `Framed` file.
+pub(crate) fn read_len_prefixed<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 8];
+    let mut read = 0;
+    while read < len.len() {
+        match reader.read(&mut len[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated length")),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let len = u64::from_le_bytes(len);
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        let message = format!("truncated value, expected {} bytes but got {}", len, bytes.len());
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message));
+    }
+    Ok(Some(bytes))
+}
+
+/// Stores the wrapped value after its length, and checks on restore that exactly that
+/// many bytes were read back
+///
+/// The bytes produced by the inner `Storable` are preceded by their length, as a
+/// little-endian `u64`. Restoring fails if the file holds more or fewer bytes than that
+/// length, or if the inner `Storable` leaves some of them unread, which catches both
+/// damaged files and serializers that disagree with themselves.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LengthPrefixed<S>(pub S);
+
+impl<S, W, R> Storable<W, R> for LengthPrefixed<S>
+where
+    S: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut len = [0; 8];
+        reader
+            .read_exact(&mut len)
+            .map_err(|_| StorableRestoreError("missing length".to_string()))?;
+        let len = u64::from_le_bytes(len);
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        if bytes.len() as u64 != len {
+            return Err(StorableRestoreError(format!(
+                "expected {} bytes but the file holds {}",
+                len,
+                bytes.len()
+            )));
+        }
+
+        let mut unread = bytes.as_slice();
+        let value = S::restore(&mut unread)?;
+        if !unread.is_empty() {
+            return Err(StorableRestoreError(format!(
+                "{} of {} bytes were left unread",
+                unread.len(),
+                len
+            )));
+        }
+        Ok(LengthPrefixed(value))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        let bytes = to_bytes(&self.0)?;
+        writer
+            .write_all(&(bytes.len() as u64).to_le_bytes())
+            .and_then(|_| writer.write_all(&bytes))
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+
+    fn serialized_len(&self) -> Option<u64> {
+        Storable::<&mut Vec<u8>, &mut &[u8]>::serialized_len(&self.0).map(|len| len + 8)
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::storable::*;
+
+/// Stores the wrapped value in MessagePack, through its serde implementations
+///
+/// MessagePack is a compact binary format with libraries in most languages, so the
+/// files can be read by programs that are not written in Rust.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MsgPack<T>(pub T);
+
+impl<T> Deref for MsgPack<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for MsgPack<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, W, R> Storable<W, R> for MsgPack<T>
+where
+    T: Serialize + DeserializeOwned,
+    W: Write,
+    R: Read,
+{
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        rmp_serde::decode::from_read(reader)
+            .map(MsgPack)
+            .map_err(|e| StorableRestoreError(format!("invalid MessagePack: {}", e)))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        rmp_serde::encode::write(&mut writer, &self.0).map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
@@ -0,0 +1,51 @@
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+use prost::Message;
+
+use crate::storable::*;
+
+/// Stores the wrapped value as a protobuf message, through its prost implementation
+///
+/// Each file holds a single encoded message, without any length prefix, as protobuf
+/// tools expect of `.pb` files, so messages generated by prost can be shared with
+/// programs written in other languages.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Protobuf<T>(pub T);
+
+impl<T> Deref for Protobuf<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Protobuf<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, W, R> Storable<W, R> for Protobuf<T>
+where
+    T: Message + Default,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        T::decode(bytes.as_slice())
+            .map(Protobuf)
+            .map_err(|e| StorableRestoreError(format!("invalid protobuf: {}", e)))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        writer
+            .write_all(&self.0.encode_to_vec())
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
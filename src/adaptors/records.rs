@@ -0,0 +1,72 @@
+use std::io::{Read, Write};
+
+use super::read_len_prefixed;
+use crate::storable::*;
+
+/// Writes `element` as one record.
+fn write_record<S: StorableBytes, W: Write>(element: &S, writer: &mut W) -> Result<(), StorableStoreError> {
+    let bytes = to_bytes(element)?;
+    writer
+        .write_all(&(bytes.len() as u64).to_le_bytes())
+        .and_then(|_| writer.write_all(&bytes))
+        .map_err(|e| StorableStoreError(e.to_string()))
+}
+
+/// Reads the next record, returning `None` at the end of `reader`.
+fn read_record<S: StorableBytes, R: Read>(reader: &mut R) -> Result<Option<S>, StorableRestoreError> {
+    match read_len_prefixed(reader).map_err(|e| StorableRestoreError(e.to_string()))? {
+        Some(bytes) => from_bytes(&bytes).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Stores a list of values as a sequence of records, which can also be written from an
+/// iterator and read one value at a time through `StorableStream`
+///
+/// Each record holds the bytes of one value, preceded by their length as a
+/// little-endian `u64`, and the file ends with the last record.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Records<S>(pub Vec<S>);
+
+impl<S, W, R> Storable<W, R> for Records<S>
+where
+    S: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        let mut elements = Vec::new();
+        <Records<S> as StorableStream<W, R>>::restore_stream(reader, |element| elements.push(element))?;
+        Ok(Records(elements))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        self.0.iter().try_for_each(|element| write_record(element, &mut writer))
+    }
+}
+
+impl<S, W, R> StorableStream<W, R> for Records<S>
+where
+    S: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    type Item = S;
+
+    fn store_stream<I>(mut items: I, mut writer: W) -> Result<(), StorableStoreError>
+    where
+        I: Iterator<Item = S>,
+    {
+        items.try_for_each(|element| write_record(&element, &mut writer))
+    }
+
+    fn restore_stream<F>(mut reader: R, mut sink: F) -> Result<(), StorableRestoreError>
+    where
+        F: FnMut(S),
+    {
+        while let Some(element) = read_record(&mut reader)? {
+            sink(element);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+use crate::storable::*;
+
+/// A value that can be stored behind a trait object in a `TypedBox`
+///
+/// Make it a supertrait of the trait the boxed values implement, so that `TypedBox` can
+/// tell which concrete type it holds, and serialize it.
+pub trait Tagged {
+    /// Returns the tag the concrete type of the value is registered under.
+    fn type_tag(&self) -> &str;
+
+    /// Serializes the value, in the form its constructor in the `Registry` expects.
+    fn value_bytes(&self) -> Result<Vec<u8>, StorableStoreError>;
+}
+
+type Constructor<B> = Box<dyn Fn(&[u8]) -> Result<Box<B>, StorableRestoreError>>;
+
+/// The concrete types a `TypedBox<B>` can hold, each under its own tag
+///
+/// The registry is the context of `StorableWithCtx`, so `TypedBox` items are stored
+/// with `DirStorage::store_with_ctx` and restored with `DirStorage::restore_with_ctx`.
+pub struct Registry<B: ?Sized> {
+    constructors: HashMap<String, Constructor<B>>,
+}
+
+impl<B: ?Sized> Default for Registry<B> {
+    fn default() -> Registry<B> {
+        Registry::new()
+    }
+}
+
+impl<B: ?Sized> fmt::Debug for Registry<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tags: Vec<&String> = self.constructors.keys().collect();
+        tags.sort();
+        f.debug_struct("Registry").field("tags", &tags).finish()
+    }
+}
+
+impl<B: ?Sized> Registry<B> {
+    /// Creates a registry with no types.
+    pub fn new() -> Registry<B> {
+        Registry {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Registers `constructor`, which rebuilds the values tagged `tag` from the bytes
+    /// returned by `Tagged::value_bytes`, replacing any constructor already registered
+    /// under that tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` contains a nul byte, which ends the tag in files.
+    pub fn register<S, F>(mut self, tag: S, constructor: F) -> Registry<B>
+    where
+        S: Into<String>,
+        F: Fn(&[u8]) -> Result<Box<B>, StorableRestoreError> + 'static,
+    {
+        let tag = tag.into();
+        assert!(!tag.contains('\0'), "type tags cannot contain nul bytes");
+        self.constructors.insert(tag, Box::new(constructor));
+        self
+    }
+
+    /// Returns whether a constructor is registered under `tag`.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.constructors.contains_key(tag)
+    }
+}
+
+/// Stores a trait object, along with the tag of its concrete type
+///
+/// The file holds the tag returned by `Tagged::type_tag`, a nul byte, then the bytes
+/// returned by `Tagged::value_bytes`. On restore, the constructor registered under the
+/// tag rebuilds the value, so a `DirStorage<TypedBox<dyn Trait>>` can hold values of
+/// different types. Storing or restoring a value whose tag is not in the `Registry`
+/// fails.
+pub struct TypedBox<B: ?Sized>(pub Box<B>);
+
+impl<B: ?Sized + fmt::Debug> fmt::Debug for TypedBox<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedBox").field(&&*self.0).finish()
+    }
+}
+
+impl<B: ?Sized> Deref for TypedBox<B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        &self.0
+    }
+}
+
+impl<B: ?Sized> DerefMut for TypedBox<B> {
+    fn deref_mut(&mut self) -> &mut B {
+        &mut self.0
+    }
+}
+
+impl<B, W, R> StorableWithCtx<Registry<B>, W, R> for TypedBox<B>
+where
+    B: Tagged + ?Sized,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R, registry: &Registry<B>) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        let end = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| StorableRestoreError("missing type tag".to_string()))?;
+        let tag = std::str::from_utf8(&bytes[..end])
+            .map_err(|_| StorableRestoreError("type tag is not valid UTF-8".to_string()))?;
+        let constructor = registry
+            .constructors
+            .get(tag)
+            .ok_or_else(|| StorableRestoreError(format!("unknown type tag {:?}", tag)))?;
+        constructor(&bytes[end + 1..]).map(TypedBox)
+    }
+
+    fn store(&self, mut writer: W, registry: &Registry<B>) -> Result<(), StorableStoreError> {
+        let tag = self.0.type_tag();
+        if !registry.contains(tag) {
+            return Err(StorableStoreError(format!("unknown type tag {:?}", tag)));
+        }
+        let bytes = self.0.value_bytes()?;
+        let write = |writer: &mut W, bytes: &[u8]| {
+            writer.write_all(bytes).map_err(|e| StorableStoreError(e.to_string()))
+        };
+        write(&mut writer, tag.as_bytes())?;
+        write(&mut writer, &[0])?;
+        write(&mut writer, &bytes)
+    }
+}
@@ -0,0 +1,84 @@
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+use crate::storable::*;
+
+/// Stores a trailer, such as a signature or an index, after the wrapped value
+///
+/// The file holds, in order:
+///
+/// * the bytes of `value`, as its `Storable` implementation writes them,
+/// * the bytes of `trailer`, as its `Storable` implementation writes them,
+/// * the length of the trailer bytes, as a little-endian `u64`.
+///
+/// Restoring reads the length from the end of the file first, then restores the
+/// trailer, and only then the value, each from exactly its own bytes. A trailer can
+/// therefore be checked before the value is decoded, and the value never sees the
+/// trailer bytes, whatever it reads.
+///
+/// To keep the trailer out of compression or encryption, `WithTrailer` must be the
+/// outer layer, wrapping the compressed value: `WithTrailer<Base64<S>, T>` encodes its
+/// value but not its trailer, while `Base64<WithTrailer<S, T>>` encodes both. With
+/// `Options::framing`, the frame is outside of everything, so the header comes before
+/// the value and the footer after the trailer length.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WithTrailer<S, T> {
+    pub value: S,
+    pub trailer: T,
+}
+
+impl<S, T> WithTrailer<S, T> {
+    /// Wraps `value`, to store it followed by `trailer`.
+    pub fn new(value: S, trailer: T) -> WithTrailer<S, T> {
+        WithTrailer { value, trailer }
+    }
+}
+
+impl<S, T, W, R> Storable<W, R> for WithTrailer<S, T>
+where
+    S: StorableBytes,
+    T: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        let rest_len = bytes
+            .len()
+            .checked_sub(8)
+            .ok_or_else(|| StorableRestoreError("missing trailer length".to_string()))?;
+        let (rest, len) = bytes.split_at(rest_len);
+        let len = u64::from_le_bytes(len.try_into().unwrap());
+        if len > rest.len() as u64 {
+            return Err(StorableRestoreError(format!(
+                "trailer of {} bytes is longer than the {} bytes before it",
+                len,
+                rest.len()
+            )));
+        }
+
+        let (value, trailer) = rest.split_at(rest.len() - len as usize);
+        let trailer = from_bytes(trailer)?;
+        let value = from_bytes(value)?;
+        Ok(WithTrailer { value, trailer })
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        let value = to_bytes(&self.value)?;
+        let trailer = to_bytes(&self.trailer)?;
+        writer
+            .write_all(&value)
+            .and_then(|_| writer.write_all(&trailer))
+            .and_then(|_| writer.write_all(&(trailer.len() as u64).to_le_bytes()))
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+
+    fn serialized_len(&self) -> Option<u64> {
+        let value = Storable::<&mut Vec<u8>, &mut &[u8]>::serialized_len(&self.value)?;
+        let trailer = Storable::<&mut Vec<u8>, &mut &[u8]>::serialized_len(&self.trailer)?;
+        Some(value + trailer + 8)
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use zstd::stream::read::Decoder;
+
+use crate::dir::{rename_staged, stage_file, Error};
+use crate::storable::*;
+
+/// File of a directory holding the dictionary shared by its `Zstd` items.
+const DICTIONARY_FILE: &str = ".soter_zstd_dictionary";
+
+/// Compression level used by `Zstd`, zstd's default.
+const LEVEL: i32 = 3;
+
+/// A zstd dictionary, shared by every `Zstd` item of a storage
+///
+/// Each compressed file starts with the fingerprint of the dictionary it was compressed
+/// with, so restoring it with another dictionary fails instead of giving garbage. A
+/// dictionary must therefore be kept for as long as files compressed with it exist,
+/// which is easiest by saving it alongside them with `save`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ZstdDictionary {
+    bytes: Vec<u8>,
+    fingerprint: u32,
+}
+
+impl ZstdDictionary {
+    /// Creates a dictionary from `bytes`, either made by the zstd tools or used as raw
+    /// content.
+    pub fn new(bytes: Vec<u8>) -> ZstdDictionary {
+        // FNV-1a, which is enough to tell dictionaries apart.
+        let fingerprint = bytes
+            .iter()
+            .fold(0x811c_9dc5u32, |hash, &b| (hash ^ u32::from(b)).wrapping_mul(0x0100_0193));
+        ZstdDictionary { bytes, fingerprint }
+    }
+
+    /// Trains a dictionary of at most `max_size` bytes on `samples`, which should look
+    /// like the serialized items it will compress.
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<ZstdDictionary, Error> {
+        Ok(ZstdDictionary::new(zstd::dict::from_samples(samples, max_size)?))
+    }
+
+    /// Loads the dictionary saved in directory `dir_path_str` by `save`.
+    pub fn load<D: AsRef<str>>(dir_path_str: D) -> Result<ZstdDictionary, Error> {
+        let path = Path::new(dir_path_str.as_ref()).join(DICTIONARY_FILE);
+        match fs::read(path) {
+            Ok(bytes) => Ok(ZstdDictionary::new(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(Error::NotFound(DICTIONARY_FILE.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves the dictionary in directory `dir_path_str`, in a hidden file that `restore`
+    /// skips.
+    ///
+    /// The file is replaced atomically, so a dictionary saved earlier is never left
+    /// half overwritten, which would make the files compressed with it unreadable.
+    pub fn save<D: AsRef<str>>(&self, dir_path_str: D) -> Result<(), Error> {
+        let path = Path::new(dir_path_str.as_ref()).join(DICTIONARY_FILE);
+        let (tmp_path, _) = stage_file(&path, None, |mut writer| {
+            writer.write_all(&self.bytes).map_err(|e| StorableStoreError(e.to_string()))
+        })?;
+        rename_staged(&tmp_path, &path)
+    }
+
+    /// Returns the bytes of the dictionary.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Stores the wrapped value compressed with zstd and a shared dictionary
+///
+/// Compressing many small, similar items one by one gains little, since each file is
+/// too short for zstd to find repetitions in. With a dictionary trained on such items,
+/// the patterns they have in common are found in the dictionary instead.
+///
+/// The dictionary is the context of `StorableWithCtx`, so items are stored with
+/// `DirStorage::store_with_ctx` and restored with `DirStorage::restore_with_ctx`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Zstd<S>(pub S);
+
+impl<S, W, R> StorableWithCtx<ZstdDictionary, W, R> for Zstd<S>
+where
+    S: StorableBytes,
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R, dictionary: &ZstdDictionary) -> Result<Self, StorableRestoreError> {
+        let io_error = |e: io::Error| StorableRestoreError(e.to_string());
+        let mut fingerprint = [0; 4];
+        reader.read_exact(&mut fingerprint).map_err(io_error)?;
+        if u32::from_le_bytes(fingerprint) != dictionary.fingerprint {
+            return Err(StorableRestoreError(
+                "compressed with another dictionary".to_string(),
+            ));
+        }
+
+        let mut compressed = Vec::new();
+        reader.read_to_end(&mut compressed).map_err(io_error)?;
+        let mut bytes = Vec::new();
+        Decoder::with_dictionary(compressed.as_slice(), &dictionary.bytes)
+            .and_then(|mut decoder| decoder.read_to_end(&mut bytes))
+            .map_err(io_error)?;
+        from_bytes(&bytes).map(Zstd)
+    }
+
+    fn store(&self, mut writer: W, dictionary: &ZstdDictionary) -> Result<(), StorableStoreError> {
+        let io_error = |e: io::Error| StorableStoreError(e.to_string());
+        let bytes = to_bytes(&self.0)?;
+        let compressed = zstd::bulk::Compressor::with_dictionary(LEVEL, &dictionary.bytes)
+            .and_then(|mut compressor| compressor.compress(&bytes))
+            .map_err(io_error)?;
+        writer.write_all(&dictionary.fingerprint.to_le_bytes()).map_err(io_error)?;
+        writer.write_all(&compressed).map_err(io_error)
+    }
+}
@@ -1,16 +1,69 @@
+#[cfg(feature = "async")]
+mod asynch;
+mod backend;
+mod classified;
+mod compact;
+mod entry;
+mod events;
+mod framed;
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+mod formatted;
+mod glob;
+#[cfg(feature = "json")]
+mod json;
+mod key_encoding;
+mod listing;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod multi_format;
+mod name_hasher;
+mod parts;
+mod recursive;
+mod resumable;
+mod shard;
+mod shared;
+mod stream;
+mod streamed;
+#[cfg(feature = "tempfile")]
+mod temporary;
+mod writer;
+
+pub use self::backend::DirBackend;
+pub use self::classified::ErrorAction;
+pub use self::entry::Entry;
+pub use self::events::StoreEvent;
+pub use self::framed::{Framed, Frames};
+pub use self::key_encoding::KeyEncoding;
+pub use self::listing::RestoreListing;
+pub use self::multi_format::{Detector, MultiFormat, Unrecognized};
+pub use self::name_hasher::NameHasher;
+pub use self::recursive::Recursion;
+pub use self::resumable::RestoreCheckpoint;
+pub use self::shard::{ShardMove, ShardScheme};
+#[cfg(feature = "tempfile")]
+pub use self::temporary::TempGuard;
+pub use self::writer::FileWriter;
+
 use std::io;
 use std::fmt;
 
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, Read, Seek, SeekFrom, Take, Write};
 
 use std::hash::Hash;
 use std::borrow::Borrow;
 
-use std::path::Path;
-use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
 use std::error::Error as StdError;
-use std::fs::{read_dir, File, OpenOptions};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs::{self, read_dir, File, OpenOptions, ReadDir};
 
+use self::events::Listeners;
+#[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+use crate::adaptors::SerdeFormat;
+use self::writer::Flushed;
 use crate::storable::*;
 
 #[derive(Debug)]
@@ -20,16 +73,30 @@ pub enum Error {
     NotFound(String),
     StoreError(String, String),
     RestoreError(String, String),
+    KeyCollision(String),
+    VerificationFailed(String),
+    SpecialFile(String),
+    PathIsDirectory(String),
+    Unsupported(String),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::OSError(s) => write!(f, "{}", format!("{}", s)),
+            Error::OSError(s) => write!(f, "{}", s),
             Error::IOError(e) => e.fmt(f),
             Error::NotFound(filename) => write!(f, "{}: Not Found", filename),
             Error::StoreError(filename, s) => write!(f, "{}: {}", filename, s),
             Error::RestoreError(filename, s) => write!(f, "{}: {}", filename, s),
+            Error::KeyCollision(key) => write!(f, "{}: more than one key maps to it", key),
+            Error::VerificationFailed(key) => write!(f, "{}: stored file does not match", key),
+            Error::SpecialFile(filename) => write!(f, "{}: not a regular file", filename),
+            Error::PathIsDirectory(key) => write!(f, "{}: a directory is in the place of its file", key),
+            Error::Unsupported(operation) => write!(f, "{}: not supported by this storage", operation),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(e) => e.fmt(f),
         }
     }
 }
@@ -38,46 +105,1249 @@ impl StdError for Error {}
 
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
-        match e {
-            _ => Error::IOError(e),
+        Error::IOError(e)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Sqlite(e)
+    }
+}
+
+type BufReadFile = BufReader<Take<File>>;
+type BufWriteFile = FileWriter;
+
+/// File holding the last key handed out by `DirStorage::insert_next`.
+const COUNTER_FILE: &str = ".soter_counter";
+
+/// File locked while a process updates the shared state of a directory.
+const LOCK_FILE: &str = ".soter_lock";
+
+/// Directory holding the snapshots of `DirBackend::snapshot`.
+const SNAPSHOT_DIR: &str = ".snapshots";
+
+/// Takes an exclusive lock on the directory `dir_path`.
+///
+/// The lock is released when the returned file is dropped.
+fn lock_dir(dir_path: &Path) -> Result<File, Error> {
+    lock_file(&dir_path.join(LOCK_FILE))
+}
+
+/// Takes an exclusive lock on the file at `path`, creating it if needed.
+///
+/// The lock is released when the returned file is dropped.
+fn lock_file(path: &Path) -> Result<File, Error> {
+    let lock = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Opens the existing file at `path` so that its timestamps can be changed.
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // Windows only allows setting times on handles opened with FILE_WRITE_ATTRIBUTES.
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+    OpenOptions::new().access_mode(FILE_WRITE_ATTRIBUTES).open(path)
+}
+
+/// Opens the existing file at `path` so that its timestamps can be changed.
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    // Changing times only requires owning the file, not write access to it.
+    File::open(path)
+}
+
+/// Opens the file at `path` for writing, creating or truncating it.
+fn create_file(path: &Path) -> Result<(BufWriteFile, Flushed), Error> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .or(Err(Error::OSError(
+            "could not open/create new agenda file".to_string(),
+        )))?;
+    Ok(FileWriter::new(file))
+}
+
+/// What `restore` does with directory entries that are neither regular files nor
+/// directories, such as FIFOs, sockets and device files
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SpecialFiles {
+    /// Ignore them, like directories.
+    #[default]
+    Skip,
+    /// Fail with `Error::SpecialFile`.
+    Error,
+}
+
+/// How much of a store `DirStorage::store_with_mode` makes atomic
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CommitMode {
+    /// Replace each file atomically, on its own. If storing fails midway, the files
+    /// already stored keep their new contents.
+    PerFile,
+    /// Replace all the files, or none of them if any fails to be written.
+    Transactional,
+}
+
+/// Settings controlling how a `DirStorage` reads and writes its files
+///
+/// Options start from their defaults with `Options::default()`, and each setting is
+/// changed with the method of the same name.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Options {
+    special_files: SpecialFiles,
+    atomic_writes: bool,
+    preserve_permissions: bool,
+    framing: Framing,
+    key_encoding: KeyEncoding,
+    backups: usize,
+    name_hasher: Option<NameHasher>,
+    shards: ShardScheme,
+    #[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+    serde_format: Option<SerdeFormat>,
+}
+
+impl Options {
+    /// Sets how `restore` treats FIFOs, sockets and device files. They are skipped by
+    /// default, since opening them could block or fail in unexpected ways.
+    pub fn special_files(mut self, special_files: SpecialFiles) -> Options {
+        self.special_files = special_files;
+        self
+    }
+
+    /// Sets whether files are replaced atomically when stored. Disabled by default.
+    ///
+    /// When enabled, each file is first written to a hidden temporary file in the same
+    /// directory, then renamed over the old file, so readers see either the old or the
+    /// new contents, never a mix. Temporary names are unique per process, thread and
+    /// write, so concurrent writers of the same key never share one. Temporary files
+    /// left behind by a crash are removed by `DirStorage::recover`.
+    pub fn atomic_writes(mut self, atomic_writes: bool) -> Options {
+        self.atomic_writes = atomic_writes;
+        self
+    }
+
+    /// Sets whether overwriting a file keeps its permissions. Disabled by default.
+    ///
+    /// When enabled, the permissions of a file are read before it is stored and set
+    /// again on the new contents, so a mode changed by the user (the whole mode on
+    /// Unix) survives the overwrite. New files get the default permissions either way.
+    pub fn preserve_permissions(mut self, preserve_permissions: bool) -> Options {
+        self.preserve_permissions = preserve_permissions;
+        self
+    }
+
+    /// Sets the bytes written around the contents of every file. There are none by
+    /// default.
+    pub fn framing(mut self, framing: Framing) -> Options {
+        self.framing = framing;
+        self
+    }
+
+    /// Sets how keys are turned into file names. By default, they are escaped on
+    /// Windows, and used as they are elsewhere.
+    pub fn key_encoding(mut self, key_encoding: KeyEncoding) -> Options {
+        self.key_encoding = key_encoding;
+        self
+    }
+
+    /// Sets how many backups of each file are kept when it is overwritten. None are by
+    /// default.
+    ///
+    /// When set to more than 0, a copy of a file is made in the hidden `.bak`
+    /// subdirectory before the file is overwritten, named after the file and the time
+    /// of the copy, as in `.bak/<key>.<nanoseconds since the epoch>`. Only the `backups`
+    /// most recent copies of each file are kept, and older ones are removed.
+    pub fn backup_on_overwrite(mut self, backups: usize) -> Options {
+        self.backups = backups;
+        self
+    }
+
+    /// Sets the file names of items to be hashes of their keys, computed by
+    /// `name_hasher`, instead of the keys themselves. This replaces `key_encoding`.
+    ///
+    /// Keys are then recovered from the manifest that `NameHasher` describes, so other
+    /// files are not restored, and fail with `Error::RestoreError`.
+    pub fn name_hasher(mut self, name_hasher: NameHasher) -> Options {
+        self.name_hasher = Some(name_hasher);
+        self
+    }
+
+    /// Sets how item files are spread over subdirectories. They are all directly in the
+    /// directory by default.
+    ///
+    /// With a sharded scheme, items are only looked for in the subdirectories that are
+    /// not hidden, one level deep, and files directly in the directory are skipped.
+    /// `DirStorage::rebalance_shards` moves the files of a directory laid out with
+    /// another scheme. The manifest of `Options::name_hasher` stays in the directory
+    /// itself, while the backups of `Options::backup_on_overwrite` go in each shard.
+    pub fn shards(mut self, shards: ShardScheme) -> Options {
+        self.shards = shards;
+        self
+    }
+
+    /// Sets the serde format plain serde values are written in and read from by
+    /// `DirStorage::store_formatted`, `DirStorage::store_single_formatted` and
+    /// `DirStorage::restore_formatted`. There is none by default, and those fail with
+    /// `Error::Unsupported` until one is set.
+    ///
+    /// Only those methods use the format: `DirStorage::store`,
+    /// `DirStorage::store_single`, `DirStorage::restore_with_options` and the others go through
+    /// the `Storable` impl of the items, and ignore it. Items that are only serde types
+    /// are not `Storable`, so they cannot be stored by those methods by mistake.
+    #[cfg(any(feature = "json", feature = "bincode", feature = "rmp-serde"))]
+    pub fn serde_format(mut self, serde_format: SerdeFormat) -> Options {
+        self.serde_format = Some(serde_format);
+        self
+    }
+
+    /// Returns the path of the file of item `key` in directory `dir_path`.
+    pub(crate) fn path_of(&self, dir_path: &Path, key: &str) -> PathBuf {
+        match self.name_hasher {
+            Some(name_hasher) => self.shards.path_for(dir_path, &name_hasher.hash(key)),
+            None => self.shards.path_for(dir_path, &self.key_encoding.encode(key)),
+        }
+    }
+
+    /// Makes sure the file of item `key` in directory `dir_path` can be written, and
+    /// its key recovered when restoring, before the file is written.
+    pub(crate) fn record_key(&self, dir_path: &Path, key: &str) -> Result<(), Error> {
+        self.record_keys(dir_path, Some(key))
+    }
+
+    /// Makes sure the files of all the items of `keys` in directory `dir_path` can be
+    /// written, and their keys recovered when restoring, like `record_key`, updating
+    /// the manifest at most once.
+    pub(crate) fn record_keys<'k, I>(&self, dir_path: &Path, keys: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'k str>,
+    {
+        let keys: Vec<&str> = keys.into_iter().collect();
+        if !self.shards.is_flat() {
+            let mut shards = HashSet::new();
+            for key in &keys {
+                if let Some(shard) = self.path_of(dir_path, key).parent() {
+                    if shards.insert(shard.to_path_buf()) {
+                        fs::create_dir_all(shard)?;
+                    }
+                }
+            }
+        }
+        match self.name_hasher {
+            Some(name_hasher) => {
+                name_hasher::record_names(dir_path, keys.into_iter().map(|key| (name_hasher.hash(key), key)))
+            }
+            None => Ok(()),
         }
     }
 }
 
-type BufReadFile = BufReader<File>;
-type BufWriteFile = BufWriter<File>;
+/// Fixed bytes written before and after the contents of every file, such as the magic
+/// number of a file format
+///
+/// Items are stored and restored without their header and footer, which are added
+/// and stripped by the `DirStorage`. When restoring, the header and footer of each
+/// file are checked by default, and a file that does not have them fails with
+/// `Error::RestoreError`. Without the check, the same number of bytes is stripped
+/// whatever they are.
+///
+/// To keep the footer out of them, items are restored through a `BufReader<Take<File>>`,
+/// so `Storable` impls for `BufReader<File>` no longer apply to `DirStorage`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Framing {
+    header: Vec<u8>,
+    footer: Vec<u8>,
+    unchecked: bool,
+}
 
-/// A storage that stores each entry in a file inside a directory
-#[derive(Debug, Eq, PartialEq)]
-pub struct DirStorage<T>
+impl Framing {
+    /// Creates a framing writing `header` before, and `footer` after, each item.
+    pub fn new<H, F>(header: H, footer: F) -> Framing
+    where
+        H: Into<Vec<u8>>,
+        F: Into<Vec<u8>>,
+    {
+        Framing {
+            header: header.into(),
+            footer: footer.into(),
+            unchecked: false,
+        }
+    }
+
+    /// Sets whether the header and footer of a file are checked when it is restored.
+    pub fn verify(mut self, verify: bool) -> Framing {
+        self.unchecked = !verify;
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.footer.is_empty()
+    }
+
+    /// Checks `header` and `footer`, read from the file at `path`, if this framing
+    /// asks so.
+    fn check(&self, path: &Path, header: &[u8], footer: &[u8]) -> Result<(), Error> {
+        let mismatch = |what| Error::RestoreError(path.display().to_string(), format!("{} does not match", what));
+        if self.unchecked {
+            Ok(())
+        } else if header != self.header.as_slice() {
+            Err(mismatch("header"))
+        } else if footer != self.footer.as_slice() {
+            Err(mismatch("footer"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The error for a file at `path` that cannot even hold its header and footer.
+fn too_short(path: &Path) -> Error {
+    Error::RestoreError(path.display().to_string(), "file is too short for its framing".to_string())
+}
+
+/// Directory holding the backups made by `Options::backup_on_overwrite`.
+const BACKUP_DIR: &str = ".bak";
+
+/// Copies the file at `path`, if any, to the backup directory next to it, and removes
+/// its backups beyond the `keep` most recent ones.
+fn backup_file(path: &Path, keep: usize) -> Result<(), Error> {
+    if keep == 0 || !path.is_file() {
+        return Ok(());
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let backup_dir = path.with_file_name(BACKUP_DIR);
+    fs::create_dir_all(&backup_dir)?;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    fs::copy(path, backup_dir.join(format!("{}.{:020}", name, nanos)))?;
+
+    let mut backups = Vec::new();
+    for entry in read_dir(&backup_dir)? {
+        let backup = entry?.file_name().to_string_lossy().into_owned();
+        if backup_owner(&backup) == Some(&*name) {
+            backups.push(backup);
+        }
+    }
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for backup in &backups[..excess] {
+        fs::remove_file(backup_dir.join(backup))?;
+    }
+    Ok(())
+}
+
+/// Returns the name of the file that the backup named `backup` is a copy of, if it is
+/// named like the backups of `backup_file`.
+fn backup_owner(backup: &str) -> Option<&str> {
+    let (name, stamp) = backup.rsplit_once('.')?;
+    if stamp.is_empty() || !stamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(name)
+}
+
+/// Returns a unique temporary path, in the same directory as `path`, to write `path`
+/// atomically.
+///
+/// Temporary names look like `.<key>.<pid>-<sequence>-<nanos>.tmp`.
+fn temp_path(path: &Path) -> PathBuf {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+    let key = path.file_name().unwrap_or_default().to_string_lossy();
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    let name = format!(".{}.{}-{}-{}.tmp", key, process::id(), sequence, nanos);
+    path.with_file_name(name)
+}
+
+/// Returns whether `filename` is a temporary file made by `temp_path`.
+fn is_temp_file(filename: &str) -> bool {
+    filename.starts_with('.') && filename.ends_with(".tmp") && filename.matches('.').count() >= 3
+}
+
+/// Restores an item from the file at `path`, stripping the framing of `options`.
+pub(crate) fn restore_file<T>(path: &Path, options: &Options) -> Result<T, Error>
 where
     T: Storable<BufWriteFile, BufReadFile>,
 {
-    storage: HashMap<String, T>,
+    read_file(path, options).map(|(object, _)| object)
 }
 
-impl<T> Default for DirStorage<T>
+/// Restores an item from the file at `path`, stripping the framing of `options`, also
+/// returning the size of the file.
+fn read_file<T>(path: &Path, options: &Options) -> Result<(T, u64), Error>
 where
     T: Storable<BufWriteFile, BufReadFile>,
 {
-    fn default() -> DirStorage<T> {
-        let storage = HashMap::new();
-        DirStorage::new(storage)
+    let (reader, len) = open_item(path, &options.framing)?;
+    let object = Storable::<BufWriteFile, BufReadFile>::restore_from_dir(reader, dir_of(path))
+        .map_err(|e| Error::RestoreError(path.display().to_string(), e.0))?;
+    Ok((object, len))
+}
+
+/// Returns the directory holding the file at `path`.
+fn dir_of(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+/// Opens the file at `path` for reading what is inside `framing`, after checking it,
+/// and returns a reader of that along with the size of the file.
+fn open_item(path: &Path, framing: &Framing) -> Result<(BufReadFile, u64), Error> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if framing.is_empty() {
+        return Ok((BufReader::new(file.take(len)), len));
     }
+
+    let (header_len, footer_len) = (framing.header.len() as u64, framing.footer.len() as u64);
+    let payload_len = len.checked_sub(header_len + footer_len).ok_or_else(|| too_short(path))?;
+    let mut header = vec![0; framing.header.len()];
+    file.read_exact(&mut header)?;
+    let mut footer = vec![0; framing.footer.len()];
+    file.seek(SeekFrom::Start(header_len + payload_len))?;
+    file.read_exact(&mut footer)?;
+    framing.check(path, &header, &footer)?;
+    file.seek(SeekFrom::Start(header_len))?;
+    Ok((BufReader::new(file.take(payload_len)), len))
 }
 
-impl<T> DirStorage<T>
+/// Stores `storable`, the item of key `key`, to the file at `path` following `options`,
+/// replacing its contents.
+pub(crate) fn store_file<T>(path: &Path, key: &str, storable: &T, options: &Options) -> Result<(), Error>
 where
     T: Storable<BufWriteFile, BufReadFile>,
 {
+    write_file(path, key, options, |writer| storable.store_in_dir(writer, dir_of(path))).map(|_| ())
+}
+
+/// Writes the file at `path`, that of key `key`, with `store` following `options`,
+/// replacing its contents, and returns the size of the file.
+///
+/// Returns `Error::PathIsDirectory` without writing anything if `path` is a directory.
+fn write_file<F>(path: &Path, key: &str, options: &Options, store: F) -> Result<u64, Error>
+where
+    F: FnOnce(BufWriteFile) -> Result<(), StorableStoreError>,
+{
+    let permissions = check_target(path, key, options)?;
+    backup_file(path, options.backups)?;
+    let store = framed(&options.framing, store);
+    if !options.atomic_writes {
+        let (writer, flushed) = create_file(path)?;
+        let file = writer.get_ref().try_clone()?;
+        store(writer).map_err(|e| Error::StoreError(path.display().to_string(), e.0))?;
+        flushed.check()?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        return Ok(file.metadata()?.len());
+    }
+
+    let (tmp_path, len) = stage_file(path, permissions, store)?;
+    rename_staged(&tmp_path, path)?;
+    Ok(len)
+}
 
+/// Wraps `store` so that it writes the header and footer of `framing` around the item.
+fn framed<'a, F>(framing: &'a Framing, store: F) -> impl FnOnce(BufWriteFile) -> Result<(), StorableStoreError> + 'a
+where
+    F: FnOnce(BufWriteFile) -> Result<(), StorableStoreError> + 'a,
+{
+    move |mut writer: BufWriteFile| {
+        if framing.is_empty() {
+            return store(writer);
+        }
+        let io_error = |e: io::Error| StorableStoreError(e.to_string());
+        writer.write_all(&framing.header).map_err(io_error)?;
+        writer.flush().map_err(io_error)?;
+        // The clone shares the position of the file, so the footer goes after the item.
+        let mut file = writer.get_ref().try_clone().map_err(io_error)?;
+        store(writer)?;
+        file.write_all(&framing.footer).map_err(io_error)
+    }
+}
+
+/// Checks that `path`, the file of key `key`, can be written, and returns the
+/// permissions its new contents should get, if `options` asks to keep them.
+///
+/// Returns `Error::PathIsDirectory` with `key` if `path` is a directory.
+fn check_target(path: &Path, key: &str, options: &Options) -> Result<Option<fs::Permissions>, Error> {
+    if path.is_dir() {
+        return Err(Error::PathIsDirectory(key.to_string()));
+    }
+    Ok(match fs::metadata(path) {
+        Ok(metadata) if options.preserve_permissions => Some(metadata.permissions()),
+        _ => None,
+    })
+}
+
+/// Writes the future contents of `path` with `store` to a new temporary file, gives it
+/// `permissions` if any, and returns its path and size.
+///
+/// The temporary file is removed if anything fails.
+pub(crate) fn stage_file<F>(
+    path: &Path,
+    permissions: Option<fs::Permissions>,
+    store: F,
+) -> Result<(PathBuf, u64), Error>
+where
+    F: FnOnce(BufWriteFile) -> Result<(), StorableStoreError>,
+{
+    let tmp_path = temp_path(path);
+    let file = OpenOptions::new().write(true).create_new(true).open(&tmp_path)?;
+    let result = (|| {
+        let (writer, flushed) = FileWriter::new(file.try_clone()?);
+        store(writer).map_err(|e| Error::StoreError(path.display().to_string(), e.0))?;
+        flushed.check()?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+        Ok(file.metadata()?.len())
+    })();
+    match result {
+        Ok(len) => Ok((tmp_path, len)),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Renames the temporary file `tmp_path`, made by `stage_file`, to `path`.
+///
+/// The temporary file is removed if the rename fails.
+pub(crate) fn rename_staged(tmp_path: &Path, path: &Path) -> Result<(), Error> {
+    fs::rename(tmp_path, path).map_err(|e| {
+        let _ = fs::remove_file(tmp_path);
+        e.into()
+    })
+}
+
+/// Counters of the operations done by a `DirStorage`
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct StoreStats {
+    /// Number of times the whole storage was restored from a directory.
+    pub restores: u64,
+    /// Number of times the whole storage was stored to a directory.
+    pub stores: u64,
+    /// Number of files restored.
+    pub files_read: u64,
+    /// Number of files stored.
+    pub files_written: u64,
+    /// Total size of the files restored.
+    pub bytes_read: u64,
+    /// Total size of the files stored.
+    pub bytes_written: u64,
+    /// Number of operations that failed.
+    pub errors: u64,
+}
+
+/// The counters of a `DirStorage`, which operations taking `&self` update, even from
+/// several threads at once.
+#[derive(Debug, Default)]
+struct Stats {
+    restores: AtomicU64,
+    stores: AtomicU64,
+    files_read: AtomicU64,
+    files_written: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Stats {
+    fn get(&self) -> StoreStats {
+        StoreStats {
+            restores: self.restores.load(Ordering::Relaxed),
+            stores: self.stores.load(Ordering::Relaxed),
+            files_read: self.files_read.load(Ordering::Relaxed),
+            files_written: self.files_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    fn set(&self, stats: StoreStats) {
+        self.restores.store(stats.restores, Ordering::Relaxed);
+        self.stores.store(stats.stores, Ordering::Relaxed);
+        self.files_read.store(stats.files_read, Ordering::Relaxed);
+        self.files_written.store(stats.files_written, Ordering::Relaxed);
+        self.bytes_read.store(stats.bytes_read, Ordering::Relaxed);
+        self.bytes_written.store(stats.bytes_written, Ordering::Relaxed);
+        self.errors.store(stats.errors, Ordering::Relaxed);
+    }
+
+    /// Adds every counter of `delta` to the counters.
+    fn add(&self, delta: StoreStats) {
+        self.restores.fetch_add(delta.restores, Ordering::Relaxed);
+        self.stores.fetch_add(delta.stores, Ordering::Relaxed);
+        self.files_read.fetch_add(delta.files_read, Ordering::Relaxed);
+        self.files_written.fetch_add(delta.files_written, Ordering::Relaxed);
+        self.bytes_read.fetch_add(delta.bytes_read, Ordering::Relaxed);
+        self.bytes_written.fetch_add(delta.bytes_written, Ordering::Relaxed);
+        self.errors.fetch_add(delta.errors, Ordering::Relaxed);
+    }
+}
+
+/// Iterates over the files of a directory that hold stored items.
+///
+/// Each file is yielded along with its key. Directories and hidden files, including the
+/// temporary files of atomic writes, are skipped,
+/// and special files are handled as `options` says. Symbolic links are followed.
+/// With sharded `Options::shards`, the files of the subdirectories are yielded instead
+/// of those of the directory. A path that is not a directory yields nothing.
+pub(crate) struct ItemFiles<'a> {
+    entries: Option<ReadDir>,
+    sharded: bool,
+    in_shards: bool,
+    shards: Vec<PathBuf>,
+    options: &'a Options,
+    manifest: Option<HashMap<String, String>>,
+}
+
+impl<'a> ItemFiles<'a> {
+    pub(crate) fn new(path: &Path, options: &'a Options) -> Result<ItemFiles<'a>, Error> {
+        let entries = if path.is_dir() {
+            Some(read_dir(path)?)
+        } else {
+            None
+        };
+        let manifest = match options.name_hasher {
+            Some(_) if entries.is_some() => Some(name_hasher::load_manifest(path)?),
+            _ => None,
+        };
+        Ok(ItemFiles {
+            entries,
+            sharded: !options.shards.is_flat(),
+            in_shards: false,
+            shards: Vec::new(),
+            options,
+            manifest,
+        })
+    }
+
+    fn key_of(&self, name: &str, file_path: &Path) -> Result<String, Error> {
+        match &self.manifest {
+            Some(manifest) => manifest
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::RestoreError(file_path.display().to_string(), "not in the manifest".to_string())),
+            None => Ok(self.options.key_encoding.decode(name).into_owned()),
+        }
+    }
+}
+
+impl<'a> Iterator for ItemFiles<'a> {
+    type Item = Result<(String, PathBuf), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // With shards, the directory itself only lists the shards.
+            let listing_shards = self.sharded && !self.in_shards;
+            // A shard that could not be read leaves no entries, and the next one follows.
+            let entries = match self.entries.as_mut() {
+                Some(entries) => Some(entries),
+                None if self.in_shards => None,
+                None => return None,
+            };
+            for entry in entries.into_iter().flatten() {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let name = match entry.file_name().into_string() {
+                    Ok(name) if !name.starts_with('.') => name,
+                    _ => continue,
+                };
+
+                let file_path = entry.path();
+                let file_type = match entry.file_type() {
+                    Ok(file_type) if file_type.is_symlink() => fs::metadata(&file_path).map(|m| m.file_type()),
+                    file_type => file_type,
+                };
+                match file_type {
+                    Ok(file_type) if file_type.is_file() && listing_shards => continue,
+                    Ok(file_type) if file_type.is_file() => {
+                        return Some(self.key_of(&name, &file_path).map(|key| (key, file_path)))
+                    }
+                    Ok(file_type) if file_type.is_dir() => {
+                        if listing_shards {
+                            self.shards.push(file_path);
+                        }
+                        continue;
+                    }
+                    Ok(_) => match self.options.special_files {
+                        SpecialFiles::Skip => continue,
+                        SpecialFiles::Error => {
+                            return Some(Err(Error::SpecialFile(file_path.display().to_string())))
+                        }
+                    },
+                    Err(e) => return Some(Err(e.into())),
+                }
+            }
+
+            // Once the directory itself is listed, its shards are listed one at a time.
+            self.in_shards = self.sharded;
+            match self.shards.pop() {
+                Some(shard) => match read_dir(&shard) {
+                    Ok(entries) => self.entries = Some(entries),
+                    Err(e) => {
+                        self.entries = None;
+                        return Some(Err(e.into()));
+                    }
+                },
+                None => {
+                    self.entries = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// A storage that stores each entry in a file inside a directory
+///
+/// Two `DirStorage` instances are equal if they contain equal items under the same keys.
+#[derive(Debug)]
+pub struct DirStorage<T> {
+    storage: HashMap<String, T>,
+    options: Options,
+    verify: Option<fn(&T, &T) -> bool>,
+    stats: Stats,
+    listeners: Listeners,
+}
+
+impl<T: PartialEq> PartialEq for DirStorage<T> {
+    fn eq(&self, other: &DirStorage<T>) -> bool {
+        self.storage == other.storage
+    }
+}
+
+impl<T: Eq> Eq for DirStorage<T> {}
+
+impl<T> Default for DirStorage<T> {
+    fn default() -> DirStorage<T> {
+        let storage = HashMap::new();
+        DirStorage::new(storage)
+    }
+}
+
+impl<T> DirStorage<T> {
     /// Constructs a new `DirStorage` from a `HashMap`.
     pub fn new(storage: HashMap<String, T>) -> DirStorage<T> {
         DirStorage {
             storage,
+            options: Options::default(),
+            verify: None,
+            stats: Stats::default(),
+            listeners: Listeners::default(),
+        }
+    }
+
+    /// Constructs a new `DirStorage` from a `HashMap`, that follows `options`.
+    pub fn with_options(storage: HashMap<String, T>, options: Options) -> DirStorage<T> {
+        DirStorage {
+            options,
+            ..DirStorage::new(storage)
         }
     }
 
+    /// Returns the options this storage follows.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Replaces the options this storage follows.
+    pub fn set_options(&mut self, options: Options) {
+        self.options = options;
+    }
+
+    /// Returns the operations counted since this storage was created, or since the last
+    /// call to `reset_stats`.
+    pub fn stats(&self) -> StoreStats {
+        self.stats.get()
+    }
+
+    /// Sets all the counters returned by `stats` back to zero.
+    pub fn reset_stats(&self) {
+        self.stats.set(StoreStats::default());
+    }
+
+    /// Adds to the counters what `update` adds to zeroed counters.
+    fn update_stats<F: FnOnce(&mut StoreStats)>(&self, update: F) {
+        let mut delta = StoreStats::default();
+        update(&mut delta);
+        self.stats.add(delta);
+    }
+
+    /// Counts `result` as a failed operation if it is an error.
+    fn count_error<R>(&self, result: Result<R, Error>) -> Result<R, Error> {
+        if result.is_err() {
+            self.update_stats(|stats| stats.errors += 1);
+        }
+        result
+    }
+
+    /// Sets whether `store_single` checks every file after writing it.
+    ///
+    /// When enabled, each file is restored right after it is written, and
+    /// `Error::VerificationFailed` is returned if the restored item is not equal to the
+    /// one in memory. This catches silent write corruption at the cost of reading back
+    /// everything that is stored. It is disabled by default.
+    pub fn set_verify_after_write(&mut self, verify: bool)
+    where
+        T: PartialEq,
+    {
+        self.verify = if verify { Some(T::eq) } else { None };
+    }
+
+    /// Sets whether `store_single` checks every file after writing it, like
+    /// `set_verify_after_write`, comparing the restored item with `eq` rather than
+    /// `PartialEq`.
+    ///
+    /// Passing `storable_eq` compares what the items are serialized into, which suits
+    /// types whose fields are not all stored.
+    pub fn set_verify_after_write_with(&mut self, eq: Option<fn(&T, &T) -> bool>) {
+        self.verify = eq;
+    }
+
+    /// Inserts `v` under the next available numeric key of the directory `dir_path_str`
+    /// and returns that key.
+    ///
+    /// The new key is one more than the largest numeric key found in memory, among the
+    /// keys of the item files of `dir_path_str`, found following the options of this
+    /// storage, or in a counter file kept in that directory. The counter file is
+    /// replaced atomically while holding a lock on the directory, so two processes
+    /// calling `insert_next` on the same directory never get the same key, even before
+    /// either of them has stored its item.
+    pub fn insert_next<D>(&mut self, dir_path_str: D, v: T) -> Result<String, Error>
+    where
+        D: AsRef<str>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let _lock = lock_dir(dir_path)?;
+
+        let counter_path = dir_path.join(COUNTER_FILE);
+        let mut counter = String::new();
+        if counter_path.is_file() {
+            File::open(&counter_path)?.read_to_string(&mut counter)?;
+        }
+        let mut last = counter.trim().parse::<u64>().ok();
+
+        for item_file in ItemFiles::new(dir_path, &self.options)? {
+            let (key, _) = item_file?;
+            if let Ok(n) = key.parse::<u64>() {
+                last = last.max(Some(n));
+            }
+        }
+        for k in self.storage.keys() {
+            if let Ok(n) = k.parse::<u64>() {
+                last = last.max(Some(n));
+            }
+        }
+
+        let next = last.map(|n| n + 1).unwrap_or(1);
+        write_file(&counter_path, COUNTER_FILE, &Options::default().atomic_writes(true), |mut writer| {
+            write!(writer, "{}", next).map_err(|e| StorableStoreError(e.to_string()))
+        })?;
+
+        let k = next.to_string();
+        self.insert(k.clone(), v);
+        Ok(k)
+    }
+
+    /// Removes the temporary files that atomic writes left behind in `dir_path_str`, and
+    /// returns their names.
+    ///
+    /// Temporary files are only left behind when a process stops in the middle of a
+    /// write, so this should run while no one else writes to `dir_path_str`, for example
+    /// at startup. With sharded `Options::shards`, the temporary files of the shards are
+    /// removed too. So are the part files of interrupted `store_parts` calls, which
+    /// `restore_parts` would fail on. The files `rebalance_shards` left under hidden
+    /// names are not removed but put back where `Options::shards` puts them, and
+    /// returned as well, and so are those of a `compact_keys` call that stopped midway,
+    /// which are put back under their old names, or moved to their new ones if all of
+    /// them were already moved away.
+    pub fn recover<D>(&self, dir_path_str: D) -> Result<Vec<String>, Error>
+    where
+        D: AsRef<str>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let mut removed = self.recover_compaction(dir_path)?;
+        let mut dirs = vec![dir_path.to_path_buf()];
+        if !self.options.shards.is_flat() {
+            dirs.extend(shard::shard_dirs(dir_path)?);
+        }
+        for dir in &dirs {
+            for entry in read_dir(dir)? {
+                let entry = entry?;
+                if let Ok(filename) = entry.file_name().into_string() {
+                    if !entry.file_type()?.is_file() {
+                        continue;
+                    }
+                    if is_temp_file(&filename) {
+                        fs::remove_file(entry.path())?;
+                        removed.push(filename);
+                    } else if let Some(name) = shard::rebalanced_name(&filename).filter(|_| dir == dir_path) {
+                        let path = self.options.shards.path_for(dir_path, name);
+                        if let Some(parent) = path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::rename(entry.path(), path)?;
+                        removed.push(filename);
+                    }
+                }
+            }
+            for part in parts::stale_parts(dir)? {
+                fs::remove_file(&part)?;
+                removed.extend(part.file_name().map(|name| name.to_string_lossy().into_owned()));
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes the auxiliary files of directory `dir_path_str` whose item file no longer
+    /// exists, and returns their paths, sorted.
+    ///
+    /// Auxiliary files are the backups kept by `Options::backup_on_overwrite`, and the
+    /// part files of `store_parts`, whose parts all become orphans once one of them is
+    /// missing or from another store, like `recover` finds them. Item files are found
+    /// following the options of this storage, like `restore_with_options`, and the
+    /// backup directories, that of each shard with sharded `Options::shards`, are
+    /// removed once empty. If `dry_run` is true, the files are only returned, and
+    /// nothing is removed.
+    pub fn purge_orphans<D>(&self, dir_path_str: D, dry_run: bool) -> Result<Vec<PathBuf>, Error>
+    where
+        D: AsRef<str>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        if !dir_path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut dirs = vec![dir_path.to_path_buf()];
+        if !self.options.shards.is_flat() {
+            dirs.extend(shard::shard_dirs(dir_path)?);
+        }
+        let mut orphans = Vec::new();
+        for dir in &dirs {
+            orphans.extend(parts::stale_parts(dir)?);
+        }
+        let mut backup_dirs: Vec<PathBuf> = dirs.iter().map(|dir| dir.join(BACKUP_DIR)).collect();
+        backup_dirs.retain(|backup_dir| backup_dir.is_dir());
+        let mut item_paths = HashSet::new();
+        if !backup_dirs.is_empty() {
+            for item_file in ItemFiles::new(dir_path, &self.options)? {
+                let (_, file_path) = item_file?;
+                item_paths.insert(file_path);
+            }
+        }
+
+        let mut emptied = Vec::new();
+        for backup_dir in backup_dirs {
+            let mut kept = false;
+            for entry in read_dir(&backup_dir)? {
+                let entry = entry?;
+                let backup = entry.file_name().to_string_lossy().into_owned();
+                let owner = backup_owner(&backup).map(|name| backup_dir.with_file_name(name));
+                match owner {
+                    Some(owner) if !item_paths.contains(&owner) && entry.file_type()?.is_file() => orphans.push(entry.path()),
+                    _ => kept = true,
+                }
+            }
+            if !kept {
+                emptied.push(backup_dir);
+            }
+        }
+        orphans.sort();
+        if dry_run {
+            return Ok(orphans);
+        }
+
+        for orphan in &orphans {
+            fs::remove_file(orphan)?;
+        }
+        for backup_dir in emptied {
+            fs::remove_dir(&backup_dir)?;
+        }
+        Ok(orphans)
+    }
+
+    /// Renames the file `from_filename` of the directory `dir_path_str` to `to_filename`.
+    ///
+    /// Only the file is renamed: items in memory are left untouched. If a file named
+    /// `to_filename` already exists, it is replaced. Returns `Error::NotFound` if there
+    /// is no file named `from_filename`.
+    pub fn rename_file_raw<D, F, G>(&self, dir_path_str: D, from_filename: F, to_filename: G) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+        F: AsRef<str>,
+        G: AsRef<str>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        fs::rename(dir_path.join(from_filename.as_ref()), dir_path.join(to_filename.as_ref())).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                Error::NotFound(String::from(from_filename.as_ref()))
+            } else {
+                e.into()
+            }
+        })
+    }
+
+    /// Sets the modification time of the file of item `key` in `dir_path_str` to now,
+    /// without rewriting it.
+    ///
+    /// Returns `Error::NotFound` if there is no such file.
+    pub fn touch<D, S>(&self, dir_path_str: D, key: S) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+    {
+        let path = self.options.path_of(Path::new(dir_path_str.as_ref()), key.as_ref());
+        if !path.is_file() {
+            return Err(Error::NotFound(String::from(key.as_ref())));
+        }
+        open_for_times(&path)?.set_modified(SystemTime::now())?;
+        Ok(())
+    }
+
+    /// Returns the keys of the files in `dir_path_str` that match `pattern`, without
+    /// deleting them.
+    ///
+    /// This is the dry run of `delete_matching`, which describes the pattern syntax.
+    pub fn match_matching<D>(&self, dir_path_str: D, pattern: &str) -> Result<Vec<String>, Error>
+    where
+        D: AsRef<str>,
+    {
+        let mut keys = Vec::new();
+        for item_file in ItemFiles::new(Path::new(dir_path_str.as_ref()), &self.options)? {
+            let (key, _) = item_file?;
+            if glob::matches(pattern, &key) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Deletes the files in `dir_path_str` whose key matches `pattern`, without restoring
+    /// them, and returns their keys.
+    ///
+    /// In `pattern`, `*` matches any sequence of characters and `?` matches any single
+    /// character. Files skipped by `restore` are never deleted. Items in memory are left
+    /// untouched.
+    pub fn delete_matching<D>(&self, dir_path_str: D, pattern: &str) -> Result<Vec<String>, Error>
+    where
+        D: AsRef<str>,
+    {
+        let keys = self.match_matching(dir_path_str.as_ref(), pattern)?;
+        for key in &keys {
+            fs::remove_file(self.options.path_of(Path::new(dir_path_str.as_ref()), key))?;
+        }
+        Ok(keys)
+    }
+
+    /// Returns item associated with key `k`, if present.
+    pub fn get<Q>(&self, k: &Q) -> Option<&T>
+    where
+        String: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.storage.get(k)
+    }
+
+    /// Returns a mutable reference to the item associated with key `k`, if present.
+    pub fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut T>
+    where
+        String: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.storage.get_mut(k)
+    }
+
+    /// Inserts a new item `v` associated with key `k`.
+    ///
+    /// If `DirStorage` already contained an item associated with `k`, it will be removed
+    /// and returned.
+    pub fn insert<S>(&mut self, k: S, v: T) -> Option<T>
+    where
+        S: Into<String>
+    {
+        let key = k.into();
+        let old = self.storage.insert(key.clone(), v);
+        self.emit(StoreEvent::Inserted { key, replaced: old.is_some() });
+        old
+    }
+
+    /// Removes the item associated with key `k` from memory, and returns it.
+    ///
+    /// The file of the item, if any, is left untouched.
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<T>
+    where
+        String: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, v) = self.storage.remove_entry(k)?;
+        self.emit(StoreEvent::Removed { key });
+        Some(v)
+    }
+
+    /// Returns an arbitrary item along with its key, or `None` if the storage is empty.
+    ///
+    /// Which item is returned is unspecified, and may change between calls.
+    pub fn any(&self) -> Option<(&String, &T)> {
+        self.storage.iter().next()
+    }
+
+    /// Returns up to `n` arbitrary items along with their keys.
+    ///
+    /// Which items are returned, and in which order, is unspecified.
+    pub fn sample(&self, n: usize) -> Vec<(&String, &T)> {
+        self.storage.iter().take(n).collect()
+    }
+
+    /// Returns the number of items in memory.
+    ///
+    /// Items can be restored one at a time, with `restore_single` or `entry`, so this
+    /// may be fewer than the items of the directory, which `total_len` counts.
+    pub fn loaded_len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns the number of item files in directory `dir_path_str`, whether their items
+    /// are in memory or not.
+    ///
+    /// Files are found following the options of this storage, like
+    /// `restore_with_options`, and none is read. Items in memory that were never stored
+    /// are not counted.
+    pub fn total_len<D>(&self, dir_path_str: D) -> Result<usize, Error>
+    where
+        D: AsRef<str>,
+    {
+        let mut len = 0;
+        for item_file in ItemFiles::new(Path::new(dir_path_str.as_ref()), &self.options)? {
+            item_file?;
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    /// Returns true if the storage contains an item associated with `k`.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        String: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.storage.contains_key(k)
+    }
+
+    /// Compares the items of this storage with those of `other`, in memory.
+    ///
+    /// Two items with the same key are equal if they are equal by `PartialEq`, whatever
+    /// their files would contain. Each list of the result is sorted by key.
+    pub fn value_diff<'a>(&'a self, other: &'a DirStorage<T>) -> ValueDiff<'a, T>
+    where
+        T: PartialEq,
+    {
+        self.value_diff_by(other, T::eq)
+    }
+
+    /// Compares the items of this storage with those of `other`, in memory, like
+    /// `value_diff`, telling items apart with `eq` rather than `PartialEq`.
+    ///
+    /// Passing `storable_eq` finds the items whose files would differ.
+    pub fn value_diff_by<'a, F>(&'a self, other: &'a DirStorage<T>, eq: F) -> ValueDiff<'a, T>
+    where
+        F: Fn(&T, &T) -> bool,
+    {
+        let mut diff = ValueDiff {
+            only_in_self: Vec::new(),
+            only_in_other: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (key, value) in &self.storage {
+            match other.storage.get(key) {
+                Some(other_value) if !eq(value, other_value) => diff.changed.push((key, value, other_value)),
+                Some(_) => {}
+                None => diff.only_in_self.push((key, value)),
+            }
+        }
+        for (key, value) in &other.storage {
+            if !self.storage.contains_key(key) {
+                diff.only_in_other.push((key, value));
+            }
+        }
+        diff.only_in_self.sort_by_key(|&(key, _)| key);
+        diff.only_in_other.sort_by_key(|&(key, _)| key);
+        diff.changed.sort_by_key(|&(key, _, _)| key);
+        diff
+    }
+}
+
+/// An item file found by `DirStorage::iter_files_on_disk`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FileInfo {
+    /// The key of the item held by the file.
+    pub key: String,
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The size of the file, framing included.
+    pub len: u64,
+    /// When the file was last modified.
+    pub modified: SystemTime,
+}
+
+/// The sizes of the files rewritten by `DirStorage::shrink`
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ShrinkReport {
+    /// Number of files rewritten.
+    pub files: u64,
+    /// Total size of the files before they were rewritten.
+    pub bytes_before: u64,
+    /// Total size of the files after they were rewritten.
+    pub bytes_after: u64,
+}
+
+impl ShrinkReport {
+    /// Returns how many bytes were saved, or 0 if the files grew.
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// The differences between the items of two storages, returned by
+/// `DirStorage::value_diff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueDiff<'a, T> {
+    /// Items whose key is only in the storage `value_diff` was called on.
+    pub only_in_self: Vec<(&'a String, &'a T)>,
+    /// Items whose key is only in the other storage.
+    pub only_in_other: Vec<(&'a String, &'a T)>,
+    /// Keys in both storages with unequal items, along with the item of each storage.
+    pub changed: Vec<(&'a String, &'a T, &'a T)>,
+}
+
+impl<'a, T> ValueDiff<'a, T> {
+    /// Returns true if both storages hold equal items under the same keys.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl<T> DirStorage<T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
     /// Tries to create a new `DirStorage` from a path.
     ///
     /// `DirStorage` will try to read all the files in the directory specified by
@@ -88,34 +1358,194 @@ where
     /// will contain all the restored instances of `T`, using their filename as a key.
     /// The file name does not include `path_str`.
     ///
+    /// FIFOs, sockets and device files are skipped. Use `restore_with_options` to
+    /// fail on them instead.
+    ///
     /// If even one file fails, then an `Error` is returned.
     pub fn restore(path_str: &str) -> Result<DirStorage<T>, Error> {
+        DirStorage::restore_with_options(path_str, Options::default())
+    }
+
+    /// Tries to create a new `DirStorage` from a path like `restore`, following `options`.
+    ///
+    /// The returned `DirStorage` keeps `options` for later operations.
+    pub fn restore_with_options(path_str: &str, options: Options) -> Result<DirStorage<T>, Error> {
+        DirStorage::restore_mapped(path_str, options, |_, object| Ok(object))
+    }
+
+    /// Tries to create a new `DirStorage` from a directory, like `restore`, passing
+    /// every item through `f` before it is inserted.
+    ///
+    /// `f` is given the key and the restored item, and returns the item to insert. If
+    /// `f` returns an error, restoring stops and fails with that error, which can take
+    /// the key `f` owns.
+    pub fn restore_map<F>(path_str: &str, f: F) -> Result<DirStorage<T>, Error>
+    where
+        F: FnMut(String, T) -> Result<T, Error>,
+    {
+        DirStorage::restore_mapped(path_str, Options::default(), f)
+    }
+
+    fn restore_mapped<F>(path_str: &str, options: Options, mut f: F) -> Result<DirStorage<T>, Error>
+    where
+        F: FnMut(String, T) -> Result<T, Error>,
+    {
         let mut storage: HashMap<String, T> = HashMap::new();
-        let path = Path::new(path_str);
-        if path.is_dir() {
-            for entry in read_dir(path)? {
-                let entry = entry?;
-                let file_path = entry.path();
-                if entry.path().is_dir() {
-                    continue;
-                }
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        for item_file in ItemFiles::new(Path::new(path_str), &options)? {
+            let (key, file_path) = item_file?;
 
-                if entry.file_name().to_str().map(|s| s.starts_with(".")).unwrap_or(true) {
-                    continue;
-                }
+            // XXX: If one file fails to be opened, or be restored, then the whole
+            // operation also fails. Maybe it would be better if errors are ignored?
+            let (object, len) = read_file(&file_path, &options)?;
+            stats.files_read += 1;
+            stats.bytes_read += len;
+            let object = f(key.clone(), object)?;
+            storage.insert(key, object);
+        }
+        let dirstor: DirStorage<T> = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
 
-                // XXX: If one file fails to be opened, or be restored, then the whole
-                // operation also fails. Maybe it would be better if errors are ignored?
-                let file = File::open(file_path)?;
-                let reader = BufReader::new(file);
-                let object = Storable::<BufWriteFile, BufReadFile>::restore(reader).map_err(|e| {
-                    Error::RestoreError(entry.path().display().to_string(), e.0)
-                })?;
-                storage.insert(entry.file_name().into_string().unwrap().into(), object);
+    /// Tries to restore the item associated with key `filename` from the directory
+    /// `dir_path_str`, replacing any item with that key in memory.
+    ///
+    /// Returns `false`, and leaves memory untouched, if there is no such file.
+    pub fn restore_single<D, S>(&mut self, dir_path_str: D, filename: S) -> Result<bool, Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+    {
+        let path = self.options.path_of(Path::new(dir_path_str.as_ref()), filename.as_ref());
+        if !path.is_file() {
+            return Ok(false);
+        }
+        let result = read_file(&path, &self.options);
+        let (object, len) = self.count_error(result)?;
+        self.update_stats(|stats| {
+            stats.files_read += 1;
+            stats.bytes_read += len;
+        });
+        self.storage.insert(String::from(filename.as_ref()), object);
+        Ok(true)
+    }
+
+    /// Replaces the item stored for `key` in `dir_path_str` with `new`, if the stored
+    /// item is equal to `expected`, and returns whether it was replaced.
+    ///
+    /// The current item is read from disk, and `false` is returned if there is no such
+    /// file. `new` is written atomically, whatever `Options::atomic_writes` says. The
+    /// directory is locked for the whole operation, so concurrent calls, even from other
+    /// processes, cannot both succeed with the same `expected` item. The item in memory
+    /// is updated to what is on disk afterwards.
+    pub fn compare_and_swap<D, S>(&mut self, dir_path_str: D, key: S, expected: &T, new: T) -> Result<bool, Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+        T: PartialEq,
+    {
+        let _lock = lock_dir(Path::new(dir_path_str.as_ref()))?;
+        if !self.restore_single(dir_path_str.as_ref(), key.as_ref())? || self.storage[key.as_ref()] != *expected {
+            return Ok(false);
+        }
+
+        let path = self.options.path_of(Path::new(dir_path_str.as_ref()), key.as_ref());
+        let options = self.options.clone().atomic_writes(true);
+        let result = write_file(&path, key.as_ref(), &options, |writer| new.store_in_dir(writer, dir_of(&path)));
+        let len = self.count_error(result)?;
+        self.storage.insert(String::from(key.as_ref()), new);
+        self.update_stats(|stats| {
+            stats.files_written += 1;
+            stats.bytes_written += len;
+        });
+        self.emit(StoreEvent::Stored { key: String::from(key.as_ref()) });
+        Ok(true)
+    }
+
+    /// Rewrites every item file of directory `dir_path_str` as the `Storable`
+    /// implementation of `T` writes it now, and returns how much space that saved.
+    ///
+    /// Each file is restored and stored again, atomically, whatever
+    /// `Options::atomic_writes` says. This drops what older or looser encodings left in
+    /// the files, such as padding or whitespace. Items in memory are left untouched.
+    pub fn shrink<D>(&self, dir_path_str: D) -> Result<ShrinkReport, Error>
+    where
+        D: AsRef<str>,
+    {
+        let options = self.options.clone().atomic_writes(true);
+        let mut report = ShrinkReport::default();
+        for item_file in ItemFiles::new(Path::new(dir_path_str.as_ref()), &self.options)? {
+            let (key, file_path) = item_file?;
+            let result = read_file(&file_path, &self.options);
+            let (object, len): (T, _) = self.count_error(result)?;
+            let store = |writer| object.store_in_dir(writer, dir_of(&file_path));
+            let result = write_file(&file_path, &key, &options, store);
+            let new_len = self.count_error(result)?;
+            self.update_stats(|stats| {
+                stats.files_read += 1;
+                stats.bytes_read += len;
+                stats.files_written += 1;
+                stats.bytes_written += new_len;
+            });
+            report.files += 1;
+            report.bytes_before += len;
+            report.bytes_after += new_len;
+        }
+        Ok(report)
+    }
+
+    /// Returns an iterator restoring, one at a time, the items of directory
+    /// `dir_path_str` whose key starts with `prefix`.
+    ///
+    /// Items are read from disk only when the iterator reaches them, and are not kept
+    /// in this storage, so scanning a large directory uses little memory. Files are
+    /// found following the options of this storage, like `restore_with_options`, in no
+    /// particular order.
+    pub fn scan_prefix<'a>(
+        &'a self,
+        dir_path_str: &str,
+        prefix: &'a str,
+    ) -> Result<impl Iterator<Item = Result<(String, T), Error>> + 'a, Error> {
+        let item_files = ItemFiles::new(Path::new(dir_path_str), &self.options)?;
+        Ok(item_files
+            .filter(move |item_file| match item_file {
+                Ok((key, _)) => key.starts_with(prefix),
+                Err(_) => true,
+            })
+            .map(move |item_file| {
+                let (key, file_path) = item_file?;
+                let result = read_file(&file_path, &self.options);
+                let (object, len) = self.count_error(result)?;
+                self.update_stats(|stats| {
+                    stats.files_read += 1;
+                    stats.bytes_read += len;
+                });
+                Ok((key, object))
+            }))
+    }
+
+    /// Restores the items of directory `dir_path_str` one at a time, and returns the
+    /// first one for which `pred` returns true, or `None` if there is none.
+    ///
+    /// Files are read lazily, like `scan_prefix`, and the search stops at the first
+    /// match, so the rest of the directory is not read. Files are visited in the order
+    /// the directory lists them, which is arbitrary, so if several items match, which one
+    /// is returned is unspecified. A file that fails to be restored fails the search.
+    pub fn find_on_disk<P>(&self, dir_path_str: &str, pred: P) -> Result<Option<(String, T)>, Error>
+    where
+        P: Fn(&str, &T) -> bool,
+    {
+        for item in self.scan_prefix(dir_path_str, "")? {
+            let (key, object) = item?;
+            if pred(&key, &object) {
+                return Ok(Some((key, object)));
             }
         }
-        let dirstor: DirStorage<T> = DirStorage { storage };
-        Ok(dirstor)
+        Ok(None)
     }
 
     /// Tries to store a `DirStorage` instance to the given directory.
@@ -126,8 +1556,73 @@ where
     where
         D: AsRef<str>,
     {
-        for path_str in self.storage.keys() {
-            self.store_single(dir_path_str.as_ref(), path_str.as_str())?;
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let result = self.options.record_keys(dir_path, self.storage.keys().map(String::as_str));
+        self.count_error(result)?;
+        for (key, storable) in &self.storage {
+            let result = self.write_recorded(dir_path, key, storable, &self.options);
+            self.count_error(result)?;
+        }
+        self.update_stats(|stats| stats.stores += 1);
+        Ok(())
+    }
+
+    /// Tries to store all the items to directory `dir_path_str`, with the atomicity
+    /// `mode` asks for, whatever `Options::atomic_writes` says.
+    ///
+    /// With `CommitMode::Transactional`, every item is first written to a temporary
+    /// file, and checked if `set_verify_after_write` asks so. Only once all of them
+    /// are written are they renamed over the old files, while holding the lock of
+    /// the directory. If writing any item fails, the directory is left untouched.
+    /// A failure of the renames themselves, or a crash while renaming, can still leave
+    /// only some of the files replaced.
+    pub fn store_with_mode<D>(&self, dir_path_str: D, mode: CommitMode) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+    {
+        let result = match mode {
+            CommitMode::PerFile => {
+                let dir_path = Path::new(dir_path_str.as_ref());
+                let options = self.options.clone().atomic_writes(true);
+                self.options.record_keys(dir_path, self.storage.keys().map(String::as_str)).and_then(|()| {
+                    self.storage
+                        .iter()
+                        .try_for_each(|(key, storable)| self.write_recorded(dir_path, key, storable, &options))
+                })
+            }
+            CommitMode::Transactional => self.store_transaction(Path::new(dir_path_str.as_ref())),
+        };
+        self.count_error(result)?;
+        self.update_stats(|stats| stats.stores += 1);
+        Ok(())
+    }
+
+    fn store_transaction(&self, dir_path: &Path) -> Result<(), Error> {
+        let _lock = lock_dir(dir_path)?;
+        self.options.record_keys(dir_path, self.storage.keys().map(String::as_str))?;
+        let mut staged = Vec::with_capacity(self.storage.len());
+        let result = self.storage.iter().try_for_each(|(key, storable)| {
+            let path = self.options.path_of(dir_path, key);
+            let permissions = check_target(&path, key, &self.options)?;
+            let store = framed(&self.options.framing, |writer| storable.store_in_dir(writer, dir_path));
+            let (tmp_path, len) = stage_file(&path, permissions, store)?;
+            staged.push((tmp_path, path, key));
+            self.update_stats(|stats| {
+                stats.files_written += 1;
+                stats.bytes_written += len;
+            });
+            self.verify_single(&staged[staged.len() - 1].0, key, storable)
+        });
+        if let Err(e) = result {
+            for (tmp_path, _, _) in &staged {
+                let _ = fs::remove_file(tmp_path);
+            }
+            return Err(e);
+        }
+        for (tmp_path, path, key) in &staged {
+            backup_file(path, self.options.backups)?;
+            rename_staged(tmp_path, path)?;
+            self.emit(StoreEvent::Stored { key: key.to_string() });
         }
         Ok(())
     }
@@ -139,59 +1634,206 @@ where
         S: AsRef<str>,
         F: AsRef<str>,
     {
-        let dir_path = Path::new(dir_path_string.as_ref());
-        let storable = self.storage.get(filename.as_ref()).ok_or(Error::NotFound(String::from(filename.as_ref())))?;
-        let new_path_buf = dir_path.join(filename.as_ref());
+        let result = self.write_single(dir_path_string.as_ref(), filename.as_ref(), &self.options);
+        self.count_error(result)
+    }
+
+    fn write_single(&self, dir_path_str: &str, filename: &str, options: &Options) -> Result<(), Error> {
+        let dir_path = Path::new(dir_path_str);
+        let storable = self.storage.get(filename).ok_or(Error::NotFound(String::from(filename)))?;
+        self.options.record_key(dir_path, filename)?;
+        self.write_recorded(dir_path, filename, storable, options)
+    }
+
+    /// Writes `storable`, the item of key `filename`, to directory `dir_path` following
+    /// `options`, once its key is recorded.
+    fn write_recorded(&self, dir_path: &Path, filename: &str, storable: &T, options: &Options) -> Result<(), Error> {
+        let new_path_buf = self.options.path_of(dir_path, filename);
         let new_path = new_path_buf.as_path();
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(new_path)
-            .or(Err(Error::OSError(
-                "could not open/create new agenda file".to_string(),
-            )))?;
-        let writer = BufWriter::new(file);
-        storable
-            .store(writer)
-            .map_err(|e| Error::RestoreError(new_path.display().to_string(), e.0))
+        let len = write_file(new_path, filename, options, |writer| storable.store_in_dir(writer, dir_path))?;
+        self.update_stats(|stats| {
+            stats.files_written += 1;
+            stats.bytes_written += len;
+        });
+        self.verify_single(new_path, filename, storable)?;
+        self.emit(StoreEvent::Stored { key: String::from(filename) });
+        Ok(())
     }
 
-    /// Returns item associated with key `k`, if present.
-    pub fn get<Q: ?Sized>(&self, k: &Q) -> Option<&T>
+    /// Checks the file at `path` against `storable`, if `set_verify_after_write` asks so.
+    fn verify_single(&self, path: &Path, filename: &str, storable: &T) -> Result<(), Error> {
+        if let Some(eq) = self.verify {
+            let (stored, len): (T, _) = read_file(path, &self.options)?;
+            self.update_stats(|stats| {
+                stats.files_read += 1;
+                stats.bytes_read += len;
+            });
+            if !eq(&stored, storable) {
+                return Err(Error::VerificationFailed(String::from(filename)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> DirStorage<T> {
+    /// Returns an iterator over the item files of directory `dir_path_str`, with their
+    /// metadata.
+    ///
+    /// No file is read, so this is cheap even for large items. Files are found following
+    /// the options of this storage, like `restore_with_options`, in no particular order.
+    pub fn iter_files_on_disk<'a>(
+        &'a self,
+        dir_path_str: &str,
+    ) -> Result<impl Iterator<Item = Result<FileInfo, Error>> + 'a, Error> {
+        let item_files = ItemFiles::new(Path::new(dir_path_str), &self.options)?;
+        Ok(item_files.map(|item_file| {
+            let (key, path) = item_file?;
+            let metadata = fs::metadata(&path)?;
+            Ok(FileInfo {
+                key,
+                len: metadata.len(),
+                modified: metadata.modified()?,
+                path,
+            })
+        }))
+    }
+
+    /// Tries to create a new `DirStorage` from a path, for types that need a context
+    /// to be restored.
+    ///
+    /// This behaves like `restore`, but every file is restored with
+    /// `StorableWithCtx::restore`, which is given `ctx`.
+    pub fn restore_with_ctx<Ctx>(path_str: &str, ctx: &Ctx) -> Result<DirStorage<T>, Error>
     where
-        String: Borrow<Q>,
-        Q: Hash + Eq,
+        T: StorableWithCtx<Ctx, BufWriteFile, BufReadFile>,
     {
-        self.storage.get(k)
+        DirStorage::restore_with_ctx_and_options(path_str, ctx, Options::default())
     }
 
-    /// Returns a mutable reference to the item associated with key `k`, if present.
-    pub fn get_mut<Q: ?Sized>(&mut self, k: &Q) -> Option<&mut T>
+    /// Tries to create a new `DirStorage` from a path like `restore_with_ctx`, following
+    /// `options` like `restore_with_options`.
+    ///
+    /// Items stored with `store_with_ctx` by a storage following `options` are restored
+    /// as they were, under keys decoded following `Options::key_encoding`, or read from
+    /// the manifest with `Options::name_hasher`. The returned `DirStorage` keeps
+    /// `options` for later operations.
+    pub fn restore_with_ctx_and_options<Ctx>(path_str: &str, ctx: &Ctx, options: Options) -> Result<DirStorage<T>, Error>
     where
-        String: Borrow<Q>,
-        Q: Hash + Eq,
+        T: StorableWithCtx<Ctx, BufWriteFile, BufReadFile>,
     {
-        self.storage.get_mut(k)
+        let mut storage: HashMap<String, T> = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        for item_file in ItemFiles::new(Path::new(path_str), &options)? {
+            let (key, file_path) = item_file?;
+            let (reader, len) = open_item(&file_path, &options.framing)?;
+            stats.files_read += 1;
+            stats.bytes_read += len;
+            let object = StorableWithCtx::<Ctx, BufWriteFile, BufReadFile>::restore(reader, ctx)
+                .map_err(|e| Error::RestoreError(file_path.display().to_string(), e.0))?;
+            storage.insert(key, object);
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
     }
 
-    /// Inserts a new item `v` associated with key `k`.
+    /// Tries to store a `DirStorage` instance to the given directory, for types that
+    /// need a context to be stored.
     ///
-    /// If `DirStorage` already contained an item associated with `k`, it will be removed
-    /// and returned.
-    pub fn insert<S>(&mut self, k: S, v: T) -> Option<T>
+    /// This behaves like `store`, but every item is stored with
+    /// `StorableWithCtx::store`, which is given `ctx`.
+    pub fn store_with_ctx<Ctx, D>(&self, dir_path_str: D, ctx: &Ctx) -> Result<(), Error>
     where
-        S: Into<String>
+        T: StorableWithCtx<Ctx, BufWriteFile, BufReadFile>,
+        D: AsRef<str>,
     {
-        self.storage.insert(k.into(), v)
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let result = self.options.record_keys(dir_path, self.storage.keys().map(String::as_str));
+        self.count_error(result)?;
+        for (key, storable) in &self.storage {
+            self.write_with_ctx(dir_path, key, storable, ctx)?;
+        }
+        self.update_stats(|stats| stats.stores += 1);
+        Ok(())
     }
 
-    /// Returns true if the storage contains an item associated with `k`.
-    pub fn contains_key<Q: ?Sized>(&self, k: &Q) -> bool
+    /// Tries to store item associated with key `filename` like `store_single`, passing
+    /// `ctx` to `StorableWithCtx::store`.
+    pub fn store_single_with_ctx<Ctx, S, F>(&self, dir_path_string: F, filename: S, ctx: &Ctx) -> Result<(), Error>
     where
-        String: Borrow<Q>,
-        Q: Hash + Eq,
+        T: StorableWithCtx<Ctx, BufWriteFile, BufReadFile>,
+        S: AsRef<str>,
+        F: AsRef<str>,
     {
-        self.storage.contains_key(k)
+        let dir_path = Path::new(dir_path_string.as_ref());
+        let result = self
+            .storage
+            .get(filename.as_ref())
+            .ok_or(Error::NotFound(String::from(filename.as_ref())))
+            .and_then(|storable| {
+                self.options.record_key(dir_path, filename.as_ref())?;
+                Ok(storable)
+            });
+        let storable = self.count_error(result)?;
+        self.write_with_ctx(dir_path, filename.as_ref(), storable, ctx)
+    }
+
+    /// Writes `storable`, the item of key `filename`, to directory `dir_path` with
+    /// `ctx`, once its key is recorded.
+    fn write_with_ctx<Ctx>(&self, dir_path: &Path, filename: &str, storable: &T, ctx: &Ctx) -> Result<(), Error>
+    where
+        T: StorableWithCtx<Ctx, BufWriteFile, BufReadFile>,
+    {
+        let path = self.options.path_of(dir_path, filename);
+        let result = write_file(&path, filename, &self.options, |writer| storable.store(writer, ctx));
+        let len = self.count_error(result)?;
+        self.update_stats(|stats| {
+            stats.files_written += 1;
+            stats.bytes_written += len;
+        });
+        self.emit(StoreEvent::Stored { key: String::from(filename) });
+        Ok(())
+    }
+}
+
+impl DirStorage<i64> {
+    /// Adds `delta` to the counter stored for `key` in `dir_path_str`, and returns the
+    /// new value.
+    ///
+    /// The current value is read from disk, or taken to be 0 if there is no such file, and
+    /// the new value is written back atomically, whatever `Options::atomic_writes` says.
+    /// The directory is locked for the whole operation, so concurrent calls, even from
+    /// other processes, never lose an increment. The item in memory is updated too.
+    pub fn fetch_add<D, S>(&mut self, dir_path_str: D, key: S, delta: i64) -> Result<i64, Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+    {
+        let _lock = lock_dir(Path::new(dir_path_str.as_ref()))?;
+        if !self.restore_single(dir_path_str.as_ref(), key.as_ref())? {
+            self.storage.insert(String::from(key.as_ref()), 0);
+        }
+
+        let value = self.storage[key.as_ref()]
+            .checked_add(delta)
+            .ok_or_else(|| Error::StoreError(String::from(key.as_ref()), "counter overflow".to_string()))?;
+        let dir_path = Path::new(dir_path_str.as_ref());
+        self.count_error(self.options.record_key(dir_path, key.as_ref()))?;
+        let path = self.options.path_of(dir_path, key.as_ref());
+        let options = self.options.clone().atomic_writes(true);
+        let store = |writer| Storable::<_, BufReadFile>::store(&value, writer);
+        let result = write_file(&path, key.as_ref(), &options, store);
+        let len = self.count_error(result)?;
+        self.storage.insert(String::from(key.as_ref()), value);
+        self.update_stats(|stats| {
+            stats.files_written += 1;
+            stats.bytes_written += len;
+        });
+        self.emit(StoreEvent::Stored { key: String::from(key.as_ref()) });
+        Ok(value)
     }
 }
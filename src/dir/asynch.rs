@@ -0,0 +1,160 @@
+//! Restoring and storing a `DirStorage` with asynchronous I/O.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter, Take};
+use tokio::task;
+
+use super::{
+    backup_file, check_target, open_item, temp_path, DirStorage, Error, Framing, ItemFiles, Options, StoreEvent,
+    StoreStats,
+};
+use crate::storable::*;
+
+type AsyncReadFile = BufReader<Take<File>>;
+type AsyncWriteFile = BufWriter<File>;
+
+impl<T> DirStorage<T>
+where
+    T: AsyncStorable<AsyncWriteFile, AsyncReadFile>,
+{
+    /// Tries to create a new `DirStorage` from a directory, like `restore`, reading the
+    /// files with asynchronous I/O.
+    ///
+    /// Items are restored with `AsyncStorable::restore`, one file at a time. This must
+    /// be run within a Tokio runtime.
+    pub async fn restore_async(path_str: &str) -> Result<DirStorage<T>, Error> {
+        DirStorage::restore_async_with_options(path_str, Options::default()).await
+    }
+
+    /// Tries to create a new `DirStorage` from a directory like `restore_async`,
+    /// following `options` like `restore_with_options`.
+    ///
+    /// The returned `DirStorage` keeps `options` for later operations.
+    pub async fn restore_async_with_options(path_str: &str, options: Options) -> Result<DirStorage<T>, Error> {
+        let dir_path = PathBuf::from(path_str);
+        let list_options = options.clone();
+        let item_files = task::spawn_blocking(move || {
+            ItemFiles::new(&dir_path, &list_options)?.collect::<Result<Vec<_>, Error>>()
+        })
+        .await
+        .map_err(|e| Error::OSError(e.to_string()))??;
+
+        let mut storage = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        for (key, file_path) in item_files {
+            let (reader, len) = open_item_async(&file_path, &options.framing).await?;
+            stats.files_read += 1;
+            stats.bytes_read += len;
+            let object = <T as AsyncStorable<AsyncWriteFile, AsyncReadFile>>::restore(reader)
+                .await
+                .map_err(|e| Error::RestoreError(file_path.display().to_string(), e.0))?;
+            storage.insert(key, object);
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
+
+    /// Tries to store all the items to directory `dir_path_str`, like `store`, writing
+    /// the files with asynchronous I/O.
+    ///
+    /// Items are stored with `AsyncStorable::store`, one file at a time, inside the
+    /// header and footer of `Options::framing`, and each file is flushed once its item
+    /// is written. With `Options::atomic_writes`, each item is
+    /// written to a new temporary file, then renamed over the old file, like `store`
+    /// does; otherwise files are written in place. Old files are backed up first if
+    /// `Options::backup_on_overwrite` asks so, and their permissions are kept if
+    /// `Options::preserve_permissions` does. This must be run within a Tokio runtime.
+    pub async fn store_async<D>(&self, dir_path_str: D) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        for (key, storable) in &self.storage {
+            self.count_error(self.options.record_key(dir_path, key))?;
+            let path = self.options.path_of(dir_path, key);
+            let result = store_file_async(&path, key, storable, &self.options).await;
+            let len = self.count_error(result)?;
+            self.update_stats(|stats| {
+                stats.files_written += 1;
+                stats.bytes_written += len;
+            });
+            self.emit(StoreEvent::Stored { key: key.clone() });
+        }
+        self.update_stats(|stats| stats.stores += 1);
+        Ok(())
+    }
+}
+
+/// Opens the file at `path` for reading what is inside `framing`, after checking it,
+/// like `open_item`, and returns a reader of that along with the size of the file.
+async fn open_item_async(path: &Path, framing: &Framing) -> Result<(AsyncReadFile, u64), Error> {
+    let (path, framing) = (path.to_path_buf(), framing.clone());
+    let (reader, len) = task::spawn_blocking(move || open_item(&path, &framing))
+        .await
+        .map_err(|e| Error::OSError(e.to_string()))??;
+    // Nothing was read through the buffer, so the file is right at the start of the item.
+    let file = reader.into_inner();
+    let payload_len = file.limit();
+    Ok((BufReader::new(File::from_std(file.into_inner()).take(payload_len)), len))
+}
+
+/// Stores `storable`, the item of key `key`, to the file at `path` following `options`,
+/// replacing its contents, and returns the size of the file.
+async fn store_file_async<T>(path: &Path, key: &str, storable: &T, options: &Options) -> Result<u64, Error>
+where
+    T: AsyncStorable<AsyncWriteFile, AsyncReadFile>,
+{
+    let permissions = check_target(path, key, options)?;
+    let (backup_path, backups) = (path.to_path_buf(), options.backups);
+    task::spawn_blocking(move || backup_file(&backup_path, backups))
+        .await
+        .map_err(|e| Error::OSError(e.to_string()))??;
+    if !options.atomic_writes {
+        let file = write_async(File::create(path).await?, path, storable, &options.framing).await?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions).await?;
+        }
+        return Ok(file.metadata().await?.len());
+    }
+
+    let tmp_path = temp_path(path);
+    let file = OpenOptions::new().write(true).create_new(true).open(&tmp_path).await?;
+    let result = async {
+        let file = write_async(file, path, storable, &options.framing).await?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions).await?;
+        }
+        file.sync_all().await?;
+        let len = file.metadata().await?.len();
+        fs::rename(&tmp_path, path).await?;
+        Ok(len)
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+/// Writes `storable`, the item of the file at `path`, to `file` inside the header and
+/// footer of `framing`, and returns the file once flushed.
+async fn write_async<T>(file: File, path: &Path, storable: &T, framing: &Framing) -> Result<File, Error>
+where
+    T: AsyncStorable<AsyncWriteFile, AsyncReadFile>,
+{
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&framing.header).await?;
+    storable
+        .store(&mut writer)
+        .await
+        .map_err(|e| Error::StoreError(path.display().to_string(), e.0))?;
+    writer.write_all(&framing.footer).await?;
+    writer.flush().await?;
+    Ok(writer.into_inner())
+}
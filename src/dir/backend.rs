@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::name_hasher::{MANIFEST_FILE, MANIFEST_LOCK_FILE};
+use super::shard::shard_dirs;
+use super::{
+    backup_file, check_target, framed, is_temp_file, lock_dir, rename_staged, restore_file, stage_file, store_file,
+    temp_path, BufReadFile, BufWriteFile, Error, ItemFiles, Options, LOCK_FILE, SNAPSHOT_DIR,
+};
+use crate::storable::*;
+use crate::storage::{BatchOp, Capabilities, SnapshotHandle, Storage};
+
+/// A `Storage` that keeps each item in a file inside a directory, and reads or writes
+/// a file only when its item is asked for
+///
+/// It uses the same layout as `DirStorage`, so a directory written by one can be used by
+/// the other. Unlike `DirStorage`, nothing is kept in memory.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DirBackend {
+    path: PathBuf,
+    options: Options,
+}
+
+impl DirBackend {
+    /// Constructs a new `DirBackend` for the directory at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> DirBackend {
+        DirBackend::with_options(path, Options::default())
+    }
+
+    /// Constructs a new `DirBackend` for the directory at `path`, that follows `options`.
+    pub fn with_options<P: Into<PathBuf>>(path: P, options: Options) -> DirBackend {
+        DirBackend {
+            path: path.into(),
+            options,
+        }
+    }
+
+    /// Returns the directory this backend uses.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Lists the paths, relative to directory `path`, of the files that a snapshot copies:
+/// the item files, those of the shards of `options`, and the hidden files holding the
+/// state of the directory, such as its manifest, but not its locks.
+fn snapshot_files(path: &Path, options: &Options) -> Result<Vec<PathBuf>, Error> {
+    let mut dirs = vec![PathBuf::new()];
+    if !options.shards.is_flat() {
+        for shard in shard_dirs(path)? {
+            dirs.extend(shard.file_name().map(PathBuf::from));
+        }
+    }
+    let mut names = Vec::new();
+    for dir in dirs {
+        for entry in fs::read_dir(path.join(&dir))? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) if name != LOCK_FILE && name != MANIFEST_LOCK_FILE && !is_temp_file(&name) => name,
+                _ => continue,
+            };
+            if fs::metadata(entry.path())?.is_file() {
+                names.push(dir.join(name));
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Returns whether the file at `name`, relative to the directory, may be written in
+/// place, so that a hard link to it would not keep its contents. Only the manifest is
+/// always replaced by a rename.
+fn written_in_place(name: &Path) -> bool {
+    name != Path::new(MANIFEST_FILE)
+}
+
+/// Makes the file at `to` have the contents of the file at `from`, by a hard link if
+/// `link` is true and the filesystem allows it, and by a copy otherwise.
+///
+/// `to` is replaced atomically, as if written with `Options::atomic_writes`.
+fn link_or_copy(from: &Path, to: &Path, link: bool) -> Result<(), Error> {
+    let tmp_path = temp_path(to);
+    let linked =
+        link && !fs::symlink_metadata(from)?.file_type().is_symlink() && fs::hard_link(from, &tmp_path).is_ok();
+    if !linked {
+        if let Err(e) = fs::copy(from, &tmp_path) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+    }
+    rename_staged(&tmp_path, to)
+}
+
+impl<T> Storage<T> for DirBackend
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error> {
+        let path = self.options.path_of(&self.path, key);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        restore_file(&path, &self.options).map(Some)
+    }
+
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.options.record_key(&self.path, key)?;
+        store_file(&self.options.path_of(&self.path, key), key, value, &self.options)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        match fs::remove_file(self.options.path_of(&self.path, key)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns whether the file of `key` exists, without reading it.
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        Ok(self.options.path_of(&self.path, key).is_file())
+    }
+
+    /// Applies `ops` while holding the lock of the directory, like
+    /// `DirStorage::store_with_mode` does with `CommitMode::Transactional`.
+    ///
+    /// Only the last change to each key counts. Every item put is first written to a
+    /// temporary file, and only once all of them are written are they renamed over the
+    /// old files, then the deleted files removed. If writing any item fails, the
+    /// directory is left untouched. A failure of the renames or removals themselves, or
+    /// a crash while making them, can still leave only some of the changes applied.
+    fn batch(&mut self, ops: Vec<BatchOp<T>>) -> Result<(), Error> {
+        let _lock = lock_dir(&self.path)?;
+        let mut changes: HashMap<String, Option<T>> = HashMap::new();
+        for op in ops {
+            match op {
+                BatchOp::Put(key, value) => changes.insert(key, Some(value)),
+                BatchOp::Delete(key) => changes.insert(key, None),
+            };
+        }
+
+        let puts = changes.iter().filter(|(_, value)| value.is_some());
+        self.options.record_keys(&self.path, puts.map(|(key, _)| key.as_str()))?;
+        let mut staged = Vec::new();
+        let result = changes.iter().try_for_each(|(key, value)| {
+            if let Some(value) = value {
+                let path = self.options.path_of(&self.path, key);
+                let permissions = check_target(&path, key, &self.options)?;
+                let store = framed(&self.options.framing, |writer| value.store_in_dir(writer, &self.path));
+                let (tmp_path, _) = stage_file(&path, permissions, store)?;
+                staged.push((tmp_path, path));
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            for (tmp_path, _) in &staged {
+                let _ = fs::remove_file(tmp_path);
+            }
+            return Err(e);
+        }
+        for (tmp_path, path) in &staged {
+            backup_file(path, self.options.backups)?;
+            rename_staged(tmp_path, path)?;
+        }
+        for (key, _) in changes.iter().filter(|(_, value)| value.is_none()) {
+            Storage::<T>::delete(self, key)?;
+        }
+        Ok(())
+    }
+
+    /// Batches are atomic as far as writing the items goes, since nothing is changed
+    /// until all of them are written.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { atomic_batch: true }
+    }
+
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        ItemFiles::new(&self.path, &self.options)?
+            .map(|item_file| item_file.map(|(key, _)| key))
+            .collect()
+    }
+
+    /// Copies the files of the directory to a new subdirectory of its hidden
+    /// `.snapshots` directory, named after the current time, while holding its lock.
+    ///
+    /// Files that are never written in place, such as the manifest of
+    /// `Options::name_hasher`, are hard linked rather than copied where the filesystem
+    /// allows it. Every other file is copied, since a hard link would share the changes
+    /// made to it in place afterwards: item files are written in place by stores without
+    /// `Options::atomic_writes`, `store_single_mapped` and `store_async` among them, and
+    /// by `Framed::append` and `Framed::repair` whatever the options say, and the
+    /// counter file by `DirStorage::insert_next`.
+    /// Subdirectories, such as backups and older snapshots, are not part of the
+    /// snapshot, except for the shards of sharded `Options::shards`.
+    fn snapshot(&self) -> Result<SnapshotHandle, Error> {
+        let _lock = lock_dir(&self.path)?;
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let snapshot_path = self
+            .path
+            .join(SNAPSHOT_DIR)
+            .join(format!("{}.{:09}", time.as_secs(), time.subsec_nanos()));
+        fs::create_dir_all(self.path.join(SNAPSHOT_DIR))?;
+        fs::create_dir(&snapshot_path)?;
+        for name in snapshot_files(&self.path, &self.options)? {
+            let to = snapshot_path.join(&name);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            link_or_copy(&self.path.join(&name), &to, !written_in_place(&name))?;
+        }
+        Ok(SnapshotHandle::new(snapshot_path))
+    }
+
+    /// Replaces the files of the directory with those of `snapshot`, each atomically,
+    /// and removes the files the snapshot does not have, while holding the lock of the
+    /// directory.
+    ///
+    /// Files are linked or copied like `snapshot` does, so writing them in place later
+    /// leaves the snapshot untouched. The directory as a whole is not replaced
+    /// atomically: a failure halfway leaves some of the files restored. The snapshot
+    /// itself is kept.
+    fn restore_snapshot(&mut self, snapshot: &SnapshotHandle) -> Result<(), Error> {
+        let _lock = lock_dir(&self.path)?;
+        let names = snapshot_files(snapshot.path(), &self.options)?;
+        for name in &names {
+            let to = self.path.join(name);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            link_or_copy(&snapshot.path().join(name), &to, !written_in_place(name))?;
+        }
+        for name in snapshot_files(&self.path, &self.options)? {
+            if !names.contains(&name) {
+                fs::remove_file(self.path.join(name))?;
+            }
+        }
+        Ok(())
+    }
+}
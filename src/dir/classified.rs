@@ -0,0 +1,99 @@
+//! Restoring directories whose I/O errors are not all fatal, such as network mounts.
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{read_file, BufReadFile, BufWriteFile, DirStorage, Error, ItemFiles, Options, StoreStats};
+use crate::storable::*;
+
+/// What `DirStorage::restore_classified` does after an I/O error
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ErrorAction {
+    /// Fail the restore with the error.
+    Fail,
+    /// Leave the file out of the storage, or the entry out of the listing.
+    Skip,
+    /// Try again: read the file again, or list the directory again from the start.
+    Retry,
+}
+
+/// Lists the item files of `path`, asking `classify` what to do with each I/O error.
+fn list<F>(path: &Path, options: &Options, classify: &mut F) -> Result<Vec<(String, PathBuf)>, Error>
+where
+    F: FnMut(&io::Error, &Path, u32) -> ErrorAction,
+{
+    let mut attempt = 1;
+    'listing: loop {
+        let mut files = Vec::new();
+        let item_files = match ItemFiles::new(path, options) {
+            Ok(item_files) => item_files,
+            Err(Error::IOError(e)) if classify(&e, path, attempt) == ErrorAction::Retry => {
+                attempt += 1;
+                continue 'listing;
+            }
+            Err(e) => return Err(e),
+        };
+        for item_file in item_files {
+            match item_file {
+                Ok(file) => files.push(file),
+                Err(Error::IOError(e)) => match classify(&e, path, attempt) {
+                    ErrorAction::Fail => return Err(Error::IOError(e)),
+                    ErrorAction::Skip => {}
+                    ErrorAction::Retry => {
+                        attempt += 1;
+                        continue 'listing;
+                    }
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(files);
+    }
+}
+
+impl<T> DirStorage<T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
+    /// Tries to create a new `DirStorage` from a directory like `restore_with_options`,
+    /// asking `classify` what to do with each I/O error instead of failing.
+    ///
+    /// `classify` is given the error, the path of the file being read, or of the
+    /// directory while it is listed, and how many times that was tried already,
+    /// starting from 1, so that it can give up after a few retries. This suits
+    /// directories on network mounts, where errors such as stale handles or timeouts
+    /// can go away on their own. Errors that are not I/O errors, such as files that
+    /// fail to be restored, still fail the restore.
+    pub fn restore_classified<F>(path_str: &str, options: Options, mut classify: F) -> Result<DirStorage<T>, Error>
+    where
+        F: FnMut(&io::Error, &Path, u32) -> ErrorAction,
+    {
+        let mut storage = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        for (key, file_path) in list(Path::new(path_str), &options, &mut classify)? {
+            let mut attempt = 1;
+            loop {
+                match read_file(&file_path, &options) {
+                    Ok((object, len)) => {
+                        stats.files_read += 1;
+                        stats.bytes_read += len;
+                        storage.insert(key, object);
+                        break;
+                    }
+                    Err(Error::IOError(e)) => match classify(&e, &file_path, attempt) {
+                        ErrorAction::Fail => return Err(Error::IOError(e)),
+                        ErrorAction::Skip => break,
+                        ErrorAction::Retry => attempt += 1,
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
+}
@@ -0,0 +1,227 @@
+//! Changing the keys of every item, both in memory and in the directory.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{name_hasher, rename_staged, stage_file, DirStorage, Error, NameHasher, StoreEvent};
+
+/// Hidden file listing the renames of a `compact_keys` call moving files to hidden
+/// names, which `recover` undoes.
+const UNDO_JOURNAL: &str = ".compacting";
+/// Hidden file listing the renames of a `compact_keys` call moving files to their new
+/// names, which `recover` finishes.
+const REDO_JOURNAL: &str = ".compacted";
+
+/// Returns the hidden name of the file at `path` between the two phases of a rename.
+fn compact_path(path: &Path) -> PathBuf {
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.compact", filename))
+}
+
+/// Writes the pairs of old and new keys of `moves` to the journal at `path`, atomically.
+fn write_journal(path: &Path, moves: &[(String, String)]) -> Result<(), Error> {
+    let (tmp_path, _) = stage_file(path, None, |mut writer| {
+        moves
+            .iter()
+            .try_for_each(|(old_key, new_key)| write!(writer, "{}\0{}\0", old_key, new_key))
+            .and_then(|()| writer.flush())
+            .map_err(|e| crate::storable::StorableStoreError(e.to_string()))
+    })?;
+    rename_staged(&tmp_path, path)
+}
+
+/// Reads the pairs of old and new keys of the journal at `path`, if there is one.
+fn read_journal(path: &Path) -> Result<Option<Vec<(String, String)>>, Error> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let corrupt = || Error::RestoreError(path.display().to_string(), "corrupt journal".to_string());
+    let contents = String::from_utf8(bytes).map_err(|_| corrupt())?;
+    let mut fields = contents.split_terminator('\0');
+    let mut moves = Vec::new();
+    while let Some(old_key) = fields.next() {
+        let new_key = fields.next().ok_or_else(corrupt)?;
+        moves.push((old_key.to_string(), new_key.to_string()));
+    }
+    Ok(Some(moves))
+}
+
+/// Replaces the old keys of `moves` with the new ones in `manifest`.
+///
+/// Returns `Error::KeyCollision` if the name of a new key holds another key.
+fn rename_in_manifest(
+    manifest: &mut HashMap<String, String>,
+    name_hasher: NameHasher,
+    moves: &[(String, String)],
+) -> Result<(), Error> {
+    for (old_key, _) in moves {
+        manifest.remove(&name_hasher.hash(old_key));
+    }
+    for (_, new_key) in moves {
+        let new_filename = name_hasher.hash(new_key);
+        match manifest.get(&new_filename) {
+            Some(recorded) if recorded != new_key => {
+                return Err(Error::KeyCollision(format!("{} ({} and {})", new_filename, recorded, new_key)))
+            }
+            _ => manifest.insert(new_filename, new_key.clone()),
+        };
+    }
+    Ok(())
+}
+
+impl<T> DirStorage<T> {
+    /// Changes the key of every item to `remap(key)`, both in memory and in the directory
+    /// `dir_path_str`.
+    ///
+    /// Files are renamed in two phases, first to a hidden temporary name and then to
+    /// their new name, so a new key may be equal to the old key of another item. With
+    /// `Options::name_hasher`, the manifest is updated for the new keys in between.
+    /// Items that have no file in `dir_path_str` are only renamed in memory.
+    ///
+    /// If two items would end up with the same key, or a file would take the place of
+    /// one that is not renamed, such as that of an item missing from memory,
+    /// `Error::KeyCollision` is returned before anything is renamed. If the first
+    /// phase fails, the files renamed so far are put back. The renames are recorded in
+    /// a hidden journal until done, so `recover` puts the files back, or finishes the
+    /// second phase, after a crash or a failure it could not undo.
+    pub fn compact_keys<D, M>(&mut self, dir_path_str: D, remap: M) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+        M: Fn(&str) -> String,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let mut renames = Vec::with_capacity(self.storage.len());
+        let mut new_keys = HashSet::with_capacity(self.storage.len());
+        for old_key in self.storage.keys() {
+            let new_key = remap(old_key);
+            if !new_keys.insert(new_key.clone()) {
+                return Err(Error::KeyCollision(new_key));
+            }
+            renames.push((old_key.clone(), new_key));
+        }
+
+        let mut moves = Vec::new();
+        let mut vacated = HashSet::new();
+        for (old_key, new_key) in &renames {
+            let old_path = self.options.path_of(dir_path, old_key);
+            if old_key != new_key && old_path.is_file() {
+                vacated.insert(old_path);
+                moves.push((old_key.clone(), new_key.clone()));
+            }
+        }
+        for (_, new_key) in &moves {
+            let new_path = self.options.path_of(dir_path, new_key);
+            if new_path.exists() && !vacated.contains(&new_path) {
+                return Err(Error::KeyCollision(new_key.clone()));
+            }
+        }
+        if let Some(name_hasher) = self.options.name_hasher {
+            rename_in_manifest(&mut name_hasher::load_manifest(dir_path)?, name_hasher, &moves)?;
+        }
+
+        if !moves.is_empty() {
+            write_journal(&dir_path.join(UNDO_JOURNAL), &moves)?;
+            if let Err(e) = self.stage_compaction(dir_path, &moves) {
+                let _ = self.undo_compaction(dir_path, &moves);
+                return Err(e);
+            }
+        }
+
+        let mut storage = HashMap::with_capacity(self.storage.len());
+        for (old_key, new_key) in &renames {
+            if let Some(v) = self.storage.remove(old_key) {
+                storage.insert(new_key.clone(), v);
+            }
+        }
+        self.storage = storage;
+        for (from, to) in renames {
+            if from != to {
+                self.emit(StoreEvent::Renamed { from, to });
+            }
+        }
+        if !moves.is_empty() {
+            self.finish_compaction(dir_path, &moves)?;
+        }
+        Ok(())
+    }
+
+    /// Undoes or finishes the `compact_keys` call that stopped in directory `dir_path`,
+    /// if any, and returns the hidden names of the files it renamed.
+    pub(crate) fn recover_compaction(&self, dir_path: &Path) -> Result<Vec<String>, Error> {
+        if let Some(moves) = read_journal(&dir_path.join(UNDO_JOURNAL))? {
+            return self.undo_compaction(dir_path, &moves);
+        }
+        match read_journal(&dir_path.join(REDO_JOURNAL))? {
+            Some(moves) => self.finish_compaction(dir_path, &moves),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Runs the first phase of the renames of `moves`, then marks the journal for the
+    /// second one.
+    fn stage_compaction(&self, dir_path: &Path, moves: &[(String, String)]) -> Result<(), Error> {
+        for (old_key, _) in moves {
+            let old_path = self.options.path_of(dir_path, old_key);
+            fs::rename(&old_path, compact_path(&old_path))?;
+        }
+        if let Some(name_hasher) = self.options.name_hasher {
+            name_hasher::update_manifest(dir_path, |manifest| {
+                rename_in_manifest(manifest, name_hasher, moves).map(|()| true)
+            })?;
+        }
+        fs::rename(dir_path.join(UNDO_JOURNAL), dir_path.join(REDO_JOURNAL))?;
+        Ok(())
+    }
+
+    /// Puts the files of `moves` back under their old names, and the old keys back in
+    /// the manifest, then removes the journal.
+    fn undo_compaction(&self, dir_path: &Path, moves: &[(String, String)]) -> Result<Vec<String>, Error> {
+        let mut renamed = Vec::new();
+        for (old_key, _) in moves {
+            let old_path = self.options.path_of(dir_path, old_key);
+            let tmp_path = compact_path(&old_path);
+            if tmp_path.is_file() {
+                fs::rename(&tmp_path, &old_path)?;
+                renamed.extend(tmp_path.file_name().map(|name| name.to_string_lossy().into_owned()));
+            }
+        }
+        if let Some(name_hasher) = self.options.name_hasher {
+            name_hasher::update_manifest(dir_path, |manifest| {
+                for (_, new_key) in moves {
+                    let new_filename = name_hasher.hash(new_key);
+                    if manifest.get(&new_filename) == Some(new_key) {
+                        manifest.remove(&new_filename);
+                    }
+                }
+                for (old_key, _) in moves {
+                    manifest.insert(name_hasher.hash(old_key), old_key.clone());
+                }
+                Ok(true)
+            })?;
+        }
+        fs::remove_file(dir_path.join(UNDO_JOURNAL))?;
+        Ok(renamed)
+    }
+
+    /// Runs the second phase of the renames of `moves`, for the files still under their
+    /// hidden names, then removes the journal.
+    fn finish_compaction(&self, dir_path: &Path, moves: &[(String, String)]) -> Result<Vec<String>, Error> {
+        let mut renamed = Vec::new();
+        for (old_key, new_key) in moves {
+            let tmp_path = compact_path(&self.options.path_of(dir_path, old_key));
+            if tmp_path.is_file() {
+                let new_path = self.options.path_of(dir_path, new_key);
+                if let Some(parent) = new_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(&tmp_path, new_path)?;
+                renamed.extend(tmp_path.file_name().map(|name| name.to_string_lossy().into_owned()));
+            }
+        }
+        fs::remove_file(dir_path.join(REDO_JOURNAL))?;
+        Ok(renamed)
+    }
+}
@@ -0,0 +1,77 @@
+//! In-place access to a single item of a `DirStorage`.
+use super::{BufReadFile, BufWriteFile, DirStorage, Error};
+use crate::storable::*;
+
+/// An item of a `DirStorage`, which may not be in memory yet, returned by
+/// `DirStorage::entry`.
+#[derive(Debug)]
+pub struct Entry<'a, T> {
+    storage: &'a mut DirStorage<T>,
+    key: String,
+}
+
+impl<T> DirStorage<T> {
+    /// Gets the entry for key `key`, to use or insert its item in place.
+    pub fn entry<S: Into<String>>(&mut self, key: S) -> Entry<'_, T> {
+        Entry {
+            storage: self,
+            key: key.into(),
+        }
+    }
+}
+
+impl<'a, T> Entry<'a, T> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Inserts `default` if there is no item in memory for this key, and returns the
+    /// item.
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if there is no item in memory for this key, and
+    /// returns the item.
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        let storage = self.storage;
+        if !storage.storage.contains_key(&self.key) {
+            storage.insert(self.key.clone(), default());
+        }
+        storage.storage.get_mut(&self.key).expect("entry was just filled")
+    }
+}
+
+impl<'a, T> Entry<'a, T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
+    /// Restores the item of this key from directory `dir_path_str` if it is not in
+    /// memory, like `DirStorage::restore_single`, and returns the item.
+    ///
+    /// If there is no file for this key either, `T::default()` is inserted.
+    pub fn or_restore<D: AsRef<str>>(self, dir_path_str: D) -> Result<&'a mut T, Error>
+    where
+        T: Default,
+    {
+        self.or_restore_with(dir_path_str, T::default)
+    }
+
+    /// Restores the item of this key from directory `dir_path_str` if it is not in
+    /// memory, like `DirStorage::restore_single`, and returns the item.
+    ///
+    /// If there is no file for this key either, the result of `default` is inserted.
+    /// Nothing is inserted if restoring the file fails.
+    pub fn or_restore_with<D, F>(self, dir_path_str: D, default: F) -> Result<&'a mut T, Error>
+    where
+        D: AsRef<str>,
+        F: FnOnce() -> T,
+    {
+        let storage = self.storage;
+        if !storage.storage.contains_key(&self.key) && !storage.restore_single(dir_path_str, &self.key)? {
+            storage.insert(self.key.clone(), default());
+        }
+        Ok(storage.storage.get_mut(&self.key).expect("entry was just filled"))
+    }
+}
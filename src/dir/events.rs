@@ -0,0 +1,59 @@
+//! Notifying listeners of the changes made to a `DirStorage`.
+use std::fmt;
+
+use super::DirStorage;
+
+/// A change made to a `DirStorage`, passed to the listeners given to
+/// `DirStorage::subscribe`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StoreEvent {
+    /// An item was inserted in memory, replacing another one if `replaced` is true.
+    Inserted { key: String, replaced: bool },
+    /// An item was removed from memory.
+    Removed { key: String },
+    /// The file of an item was written.
+    Stored { key: String },
+    /// An item was given a new key, in memory and in the directory.
+    Renamed { from: String, to: String },
+}
+
+type Listener = Box<dyn Fn(&StoreEvent) + Send + Sync>;
+
+/// The listeners of a `DirStorage`.
+#[derive(Default)]
+pub(crate) struct Listeners(Vec<Listener>);
+
+impl fmt::Debug for Listeners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Listeners({})", self.0.len())
+    }
+}
+
+impl<T> DirStorage<T> {
+    /// Calls `listener` after every change made to this storage from now on.
+    ///
+    /// Listeners are called synchronously, in the order they subscribed, once the change
+    /// is done: after `insert`, `insert_next` and the inserts of `Entry` for
+    /// `StoreEvent::Inserted`, after `remove` of an item, and after each file
+    /// successfully written by `store`, `store_single` and the like, including
+    /// `store_single_mapped` and `store_async`, for `StoreEvent::Stored`, and after
+    /// `compact_keys` for `StoreEvent::Renamed`, once per item whose key changed.
+    /// Items restored from disk trigger no event, and neither do the changes made to
+    /// the files alone, without the items in memory: `shrink`, `store_to_single_file`,
+    /// `rename_file_raw`, `delete_matching` and the like. Listeners cannot make the
+    /// change fail, so a listener forwarding events, for instance to a channel, should
+    /// ignore its own errors. Listeners are `Sync`, so that a storage with listeners
+    /// can still be shared between threads.
+    pub fn subscribe<F>(&mut self, listener: F)
+    where
+        F: Fn(&StoreEvent) + Send + Sync + 'static,
+    {
+        self.listeners.0.push(Box::new(listener));
+    }
+
+    pub(crate) fn emit(&self, event: StoreEvent) {
+        for listener in &self.listeners.0 {
+            listener(&event);
+        }
+    }
+}
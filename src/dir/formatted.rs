@@ -0,0 +1,56 @@
+//! Storing plain serde values in the format the options of a storage choose at runtime.
+//!
+//! `Options::serde_format` is only used by the methods of this module, which are the
+//! `*_formatted` counterparts of `store`, `store_single` and `restore_with_options`.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{DirStorage, Error, Options};
+use crate::adaptors::SerdeFormat;
+
+/// Returns the format `options` sets, or fails `operation` if there is none.
+fn format_of(options: &Options, operation: &str) -> Result<SerdeFormat, Error> {
+    options
+        .serde_format
+        .ok_or_else(|| Error::Unsupported(format!("{} without Options::serde_format", operation)))
+}
+
+impl<T> DirStorage<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Tries to store all the items to directory `dir_path_str` like `store`, in the
+    /// format set by `Options::serde_format`.
+    ///
+    /// This behaves like `store_with_ctx` given that format, so the items need not be
+    /// wrapped in `Serde` for their format to be chosen when the storage is created.
+    pub fn store_formatted<D>(&self, dir_path_str: D) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+    {
+        let format = format_of(&self.options, "store_formatted")?;
+        self.store_with_ctx(dir_path_str, &format)
+    }
+
+    /// Tries to store the item of key `filename` like `store_single`, in the format set
+    /// by `Options::serde_format`.
+    pub fn store_single_formatted<S, F>(&self, dir_path_string: F, filename: S) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let format = format_of(&self.options, "store_single_formatted")?;
+        self.store_single_with_ctx(dir_path_string, filename, &format)
+    }
+
+    /// Tries to create a new `DirStorage` from a path like `restore_with_options`,
+    /// reading every file in the format set by `Options::serde_format`.
+    ///
+    /// A file holding anything after its value fails the restore, and if it would be
+    /// restored in another format instead, the error names that format. The returned
+    /// `DirStorage` keeps `options`, its format included, for later operations.
+    pub fn restore_formatted(path_str: &str, options: Options) -> Result<DirStorage<T>, Error> {
+        let format = format_of(&options, "restore_formatted")?;
+        DirStorage::restore_with_ctx_and_options(path_str, &format, options)
+    }
+}
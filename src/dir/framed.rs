@@ -0,0 +1,156 @@
+//! Append-only files holding a sequence of values.
+//!
+//! A framed file is a sequence of frames, one per value. Each frame is the
+//! length of the value, as a little-endian `u64`, followed by the value as
+//! written by its `Storable` implementation. This is the same layout as a value
+//! field of a stream written by `DirStorage::export`.
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use super::Error;
+use crate::adaptors::read_len_prefixed;
+use crate::storable::*;
+
+/// A file holding a sequence of `T` values, to which values can be appended.
+///
+/// A frame can be left incomplete if the process dies while appending. Reading
+/// stops before such a frame instead of failing, and `repair` removes it so that
+/// later appends are not lost behind it.
+#[derive(Debug, Clone)]
+pub struct Framed<T> {
+    path: PathBuf,
+    marker: PhantomData<T>,
+}
+
+impl<T> Framed<T> {
+    /// Creates a `Framed` for the file at `path`. The file is created by the
+    /// first `append`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Framed<T> {
+        Framed {
+            path: path.as_ref().to_path_buf(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns an iterator over the values of the file, in the order they were
+    /// appended.
+    ///
+    /// A missing file holds no values.
+    pub fn iter(&self) -> Result<Frames<T>, Error> {
+        let reader = match File::open(&self.path) {
+            Ok(file) => Some(BufReader::new(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Frames {
+            reader,
+            path: self.path.clone(),
+            index: 0,
+            offset: 0,
+            truncated: false,
+            marker: PhantomData,
+        })
+    }
+
+    /// Cuts an incomplete trailing frame off the file.
+    ///
+    /// Returns whether the file had to be cut.
+    pub fn repair(&self) -> Result<bool, Error> {
+        let mut frames = self.iter()?;
+        while frames.next_frame()?.is_some() {}
+        if !frames.truncated {
+            return Ok(false);
+        }
+        OpenOptions::new().write(true).open(&self.path)?.set_len(frames.offset)?;
+        Ok(true)
+    }
+}
+
+impl<T> Framed<T>
+where
+    T: StorableBytes,
+{
+    /// Appends `value` to the end of the file, creating it if needed.
+    pub fn append(&self, value: &T) -> Result<(), Error> {
+        let bytes = to_bytes(value).map_err(|e| Error::StoreError(self.path.display().to_string(), e.0))?;
+        let mut frame = Vec::with_capacity(8 + bytes.len());
+        frame.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        frame.extend_from_slice(&bytes);
+
+        let mut file = OpenOptions::new().append(true).create(true).open(&self.path)?;
+        file.write_all(&frame)?;
+        Ok(())
+    }
+}
+
+/// Iterator over the values of a `Framed` file, returned by `Framed::iter`.
+#[derive(Debug)]
+pub struct Frames<T> {
+    reader: Option<BufReader<File>>,
+    path: PathBuf,
+    index: usize,
+    offset: u64,
+    truncated: bool,
+    marker: PhantomData<T>,
+}
+
+impl<T> Frames<T> {
+    /// Returns whether reading stopped at an incomplete trailing frame.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Reads the bytes of the next complete frame.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+
+        let frame = match read_len_prefixed(reader) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                self.reader = None;
+                return Ok(None);
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.truncated = true;
+                self.reader = None;
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        self.index += 1;
+        self.offset += 8 + frame.len() as u64;
+        Ok(Some(frame))
+    }
+}
+
+impl<T> Iterator for Frames<T>
+where
+    T: StorableBytes,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        let frame = match self.next_frame() {
+            Ok(frame) => frame?,
+            Err(e) => {
+                self.reader = None;
+                return Some(Err(e));
+            }
+        };
+        Some(from_bytes(&frame).map_err(|e| {
+            Error::RestoreError(format!("{} (frame {})", self.path.display(), self.index - 1), e.0)
+        }))
+    }
+}
+
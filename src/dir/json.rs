@@ -0,0 +1,35 @@
+//! Exchanging a whole storage as a single JSON document.
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{DirStorage, Error};
+
+/// Name given to the document in errors, which has no file name.
+const DOCUMENT: &str = "JSON document";
+
+impl<T> DirStorage<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Writes the items in memory to `writer`, as a single JSON object mapping each key
+    /// to its item, with keys sorted so that the same items always give the same
+    /// document.
+    ///
+    /// Unlike `JsonFormat`, which writes each item in its own file, this gathers the
+    /// whole storage, which is handy for debugging or to hand it to other programs.
+    pub fn export_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let sorted: BTreeMap<&String, &T> = self.storage.iter().collect();
+        serde_json::to_writer(writer, &sorted).map_err(|e| Error::StoreError(DOCUMENT.to_string(), e.to_string()))
+    }
+
+    /// Creates a new `DirStorage` from a JSON object read from `reader`, such as one
+    /// written by `export_json`.
+    pub fn import_json<R: Read>(reader: R) -> Result<DirStorage<T>, Error> {
+        let storage: HashMap<String, T> = serde_json::from_reader(reader)
+            .map_err(|e| Error::RestoreError(DOCUMENT.to_string(), format!("invalid JSON: {}", e)))?;
+        Ok(DirStorage::new(storage))
+    }
+}
@@ -0,0 +1,99 @@
+//! Mapping keys to file names that every platform accepts.
+use std::borrow::Cow;
+
+/// How keys are turned into file names
+///
+/// With `KeyEncoding::Windows`, a key is written as a file name by replacing some of
+/// its characters with `%` followed by the two uppercase hexadecimal digits of each of
+/// their UTF-8 bytes. The characters replaced are:
+///
+/// * `%` itself,
+/// * the characters Windows forbids in file names, `<>:"/\|?*`, and control characters,
+/// * uppercase letters, since Windows does not tell `Key` and `key` apart,
+/// * a `.` or space ending the key, which Windows would drop,
+/// * the first letter of a key naming a device, such as `CON`, `nul` or `COM1.txt`.
+///
+/// For example, the key `Con: a.` is stored in the file `%43on%3A a%2E`. Restoring
+/// reverses the replacements, and a file name that does not decode to valid UTF-8 is
+/// used as the key unchanged.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum KeyEncoding {
+    /// File names are the keys themselves. This is the default, except on Windows.
+    #[cfg_attr(not(windows), default)]
+    Plain,
+    /// File names are escaped to be valid and distinct on Windows. This is the default
+    /// on Windows.
+    #[cfg_attr(windows, default)]
+    Windows,
+}
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn is_reserved(key: &str) -> bool {
+    let stem = key.split('.').next().unwrap_or_default();
+    RESERVED_NAMES.iter().any(|name| name.eq_ignore_ascii_case(stem))
+}
+
+fn escape(c: char, name: &mut String) {
+    let mut bytes = [0; 4];
+    for byte in c.encode_utf8(&mut bytes).bytes() {
+        name.push_str(&format!("%{:02X}", byte));
+    }
+}
+
+impl KeyEncoding {
+    /// Returns the name of the file holding the item of key `key`.
+    pub fn encode<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if *self == KeyEncoding::Plain {
+            return Cow::Borrowed(key);
+        }
+
+        let reserved = is_reserved(key);
+        let last = key.char_indices().last().map(|(i, _)| i);
+        let mut name = String::with_capacity(key.len());
+        for (i, c) in key.char_indices() {
+            let forbidden = c == '%' || "<>:\"/\\|?*".contains(c) || c.is_control() || c.is_uppercase();
+            let trailing = Some(i) == last && (c == '.' || c == ' ');
+            if forbidden || trailing || (reserved && i == 0) {
+                escape(c, &mut name);
+            } else {
+                name.push(c);
+            }
+        }
+        Cow::Owned(name)
+    }
+
+    /// Returns the key of the item held by the file named `name`.
+    pub fn decode<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if *self == KeyEncoding::Plain || !name.contains('%') {
+            return Cow::Borrowed(name);
+        }
+
+        let mut bytes = Vec::with_capacity(name.len());
+        let mut rest = name.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let escaped = tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match escaped {
+                Some(escaped) if byte == b'%' => {
+                    bytes.push(escaped);
+                    rest = &tail[2..];
+                }
+                _ => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(key) => Cow::Owned(key),
+            Err(_) => Cow::Borrowed(name),
+        }
+    }
+}
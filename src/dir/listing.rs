@@ -0,0 +1,95 @@
+//! Restoring directories that may change while they are read.
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{read_file, BufReadFile, BufWriteFile, DirStorage, Error, ItemFiles, Options, StoreStats};
+use crate::storable::*;
+
+/// How `DirStorage::restore_with_listing` deals with files added or removed while it
+/// runs
+///
+/// Listings start from their defaults with `RestoreListing::default()`, which behave
+/// like `restore_with_options` except that files are all listed before any is read.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct RestoreListing {
+    skip_vanished: bool,
+    rescan: bool,
+}
+
+impl RestoreListing {
+    /// Sets whether files removed after the directory was listed, but before they were
+    /// read, are left out instead of failing the restore. Disabled by default.
+    pub fn skip_vanished(mut self, skip_vanished: bool) -> RestoreListing {
+        self.skip_vanished = skip_vanished;
+        self
+    }
+
+    /// Sets whether the directory is listed again once every file is read, to also
+    /// restore the files that appeared in the meantime. Disabled by default.
+    ///
+    /// Files appearing during this second pass are not looked for.
+    pub fn rescan(mut self, rescan: bool) -> RestoreListing {
+        self.rescan = rescan;
+        self
+    }
+}
+
+fn list(path: &Path, options: &Options) -> Result<Vec<(String, PathBuf)>, Error> {
+    ItemFiles::new(path, options)?.collect()
+}
+
+fn vanished(result: &Result<(impl Sized, u64), Error>) -> bool {
+    matches!(result, Err(Error::IOError(e)) if e.kind() == io::ErrorKind::NotFound)
+}
+
+impl<T> DirStorage<T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
+    /// Tries to create a new `DirStorage` from a directory that other processes may be
+    /// changing, like `restore_with_options`.
+    ///
+    /// The whole directory is listed first, then each file listed is read, so files
+    /// added during the restore are not picked up halfway, and `listing` says what to
+    /// do with the files that change in the meantime. This is only best effort: without
+    /// support from the operating system, such as filesystem snapshots, the items
+    /// restored can still mix contents from before and after other writes, and only
+    /// atomic writes guarantee that each file is read whole.
+    pub fn restore_with_listing(
+        path_str: &str,
+        options: Options,
+        listing: RestoreListing,
+    ) -> Result<DirStorage<T>, Error> {
+        let path = Path::new(path_str);
+        let mut storage = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        let listed = list(path, &options)?;
+        let mut seen: HashSet<String> = listed.iter().map(|(key, _)| key.clone()).collect();
+        let mut restore_listed = |files: Vec<(String, PathBuf)>| -> Result<(), Error> {
+            for (key, file_path) in files {
+                let result = read_file(&file_path, &options);
+                if listing.skip_vanished && vanished(&result) {
+                    continue;
+                }
+                let (object, len) = result?;
+                stats.files_read += 1;
+                stats.bytes_read += len;
+                storage.insert(key, object);
+            }
+            Ok(())
+        };
+        restore_listed(listed)?;
+        if listing.rescan {
+            let mut appeared = list(path, &options)?;
+            appeared.retain(|(key, _)| seen.insert(key.clone()));
+            restore_listed(appeared)?;
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
+}
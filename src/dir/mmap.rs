@@ -0,0 +1,141 @@
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use super::{
+    backup_file, check_target, dir_of, rename_staged, temp_path, BufReadFile, BufWriteFile, DirStorage, Error, StoreEvent,
+};
+use crate::storable::*;
+
+impl<T> DirStorage<T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+    T: for<'a, 'b> Storable<&'a mut &'b mut [u8], BufReadFile>,
+{
+    /// Tries to store item associated with key `filename` like `store_single`, writing it
+    /// through a memory map of the file.
+    ///
+    /// The file is first resized to the length given by `Storable::serialized_len`, then
+    /// mapped in memory, and the item is serialized straight into the mapping. Writing
+    /// more than that length fails, and writing less shrinks the file to what was
+    /// written. If `serialized_len` gives no length, or `Options::framing` is set, this is
+    /// the same as `store_single`.
+    ///
+    /// With `Options::atomic_writes`, a new temporary file is mapped, then renamed over
+    /// the old file, like `store_single` does. Otherwise the old file is resized and
+    /// written in place, so a failure to serialize the item leaves it resized, with only
+    /// part of the item in it. Either way, the old file is backed up first if
+    /// `Options::backup_on_overwrite` asks so, and its permissions are kept if
+    /// `Options::preserve_permissions` does.
+    ///
+    /// The file must not be changed by anyone else while it is being written.
+    pub fn store_single_mapped<S, F>(&self, dir_path_string: F, filename: S) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let storable = match self.storage.get(filename.as_ref()) {
+            Some(storable) => storable,
+            None => return self.store_single(dir_path_string, filename),
+        };
+        let len = match Storable::<BufWriteFile, BufReadFile>::serialized_len(storable) {
+            Some(len) if self.options.framing.is_empty() => len,
+            _ => return self.store_single(dir_path_string, filename),
+        };
+
+        let dir_path = Path::new(dir_path_string.as_ref());
+        self.count_error(self.options.record_key(dir_path, filename.as_ref()))?;
+        let path = self.options.path_of(dir_path, filename.as_ref());
+        let result = check_target(&path, filename.as_ref(), &self.options).and_then(|permissions| {
+            backup_file(&path, self.options.backups)?;
+            if self.options.atomic_writes {
+                map_staged(&path, len, storable, permissions)
+            } else {
+                map_file(&path, len, storable, permissions)
+            }
+        });
+        let written = self.count_error(result)?;
+        self.update_stats(|stats| {
+            stats.files_written += 1;
+            stats.bytes_written += written;
+        });
+        self.verify_single(&path, filename.as_ref(), storable)?;
+        self.emit(StoreEvent::Stored { key: String::from(filename.as_ref()) });
+        Ok(())
+    }
+}
+
+/// Writes `storable` through a memory map of the file at `path`, sized to `len` bytes,
+/// gives the file `permissions` if any, and returns the number of bytes written.
+fn map_file<T>(path: &Path, len: u64, storable: &T, permissions: Option<Permissions>) -> Result<u64, Error>
+where
+    T: for<'a, 'b> Storable<&'a mut &'b mut [u8], BufReadFile>,
+{
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let written = map_into(&file, path, len, storable)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    Ok(written)
+}
+
+/// Writes `storable` through a memory map of a new temporary file, sized to `len` bytes,
+/// gives it `permissions` if any, then renames it to `path`, and returns the number of
+/// bytes written.
+///
+/// The temporary file is removed if anything fails.
+fn map_staged<T>(path: &Path, len: u64, storable: &T, permissions: Option<Permissions>) -> Result<u64, Error>
+where
+    T: for<'a, 'b> Storable<&'a mut &'b mut [u8], BufReadFile>,
+{
+    let tmp_path = temp_path(path);
+    let file = OpenOptions::new().read(true).write(true).create_new(true).open(&tmp_path)?;
+    let result = map_into(&file, path, len, storable).and_then(|written| {
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+        Ok(written)
+    });
+    match result {
+        Ok(written) => rename_staged(&tmp_path, path).map(|()| written),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Writes `storable`, the item of the file at `path`, through a memory map of `file`,
+/// sized to `len` bytes, and returns the number of bytes written.
+fn map_into<T>(file: &File, path: &Path, len: u64, storable: &T) -> Result<u64, Error>
+where
+    T: for<'a, 'b> Storable<&'a mut &'b mut [u8], BufReadFile>,
+{
+    file.set_len(len)?;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    // SAFETY: the mapping is only used while this function runs, and callers must not
+    // let the file be changed behind it during that time.
+    let mut mmap = unsafe { MmapMut::map_mut(file)? };
+    let mut window: &mut [u8] = &mut mmap[..];
+    storable
+        .store_in_dir(&mut window, dir_of(path))
+        .map_err(|e| Error::StoreError(path.display().to_string(), e.0))?;
+    let written = len - window.len() as u64;
+    mmap.flush()?;
+    drop(mmap);
+
+    if written < len {
+        file.set_len(written)?;
+    }
+    Ok(written)
+}
@@ -0,0 +1,146 @@
+//! Restoring directories whose files are not all written in the same format.
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use super::{open_item, DirStorage, Error, ItemFiles, Options, StoreStats};
+use crate::storable::*;
+
+/// How `MultiFormat` recognizes the files written in one of its formats
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Detector {
+    /// The key ends with `.` followed by this extension.
+    Extension(String),
+    /// The file starts with these bytes.
+    Magic(Vec<u8>),
+}
+
+impl Detector {
+    fn matches(&self, key: &str, contents: &[u8]) -> bool {
+        match self {
+            Detector::Extension(extension) => key
+                .rsplit_once('.')
+                .is_some_and(|(_, key_extension)| key_extension == extension),
+            Detector::Magic(magic) => contents.starts_with(magic),
+        }
+    }
+}
+
+/// What `MultiFormat` does with files recognized by no format, or by more than one
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Unrecognized {
+    /// Leave them out of the storage.
+    Skip,
+    /// Fail with `Error::RestoreError`.
+    #[default]
+    Error,
+}
+
+type Decode<T> = Box<dyn Fn(&[u8]) -> Result<T, StorableRestoreError>>;
+
+/// A set of formats that items of type `T` can be restored from, used by
+/// `DirStorage::restore_multi_format`
+///
+/// Each format is a `Storable` type, along with the `Detector` that recognizes its
+/// files, and is converted to `T` once restored. A file recognized by no format, or by
+/// more than one, is skipped or fails the restore as `MultiFormat::unrecognized` says.
+pub struct MultiFormat<T> {
+    formats: Vec<(Detector, Decode<T>)>,
+    unrecognized: Unrecognized,
+}
+
+impl<T> Default for MultiFormat<T> {
+    fn default() -> MultiFormat<T> {
+        MultiFormat::new()
+    }
+}
+
+impl<T> fmt::Debug for MultiFormat<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let detectors: Vec<&Detector> = self.formats.iter().map(|(detector, _)| detector).collect();
+        f.debug_struct("MultiFormat")
+            .field("formats", &detectors)
+            .field("unrecognized", &self.unrecognized)
+            .finish()
+    }
+}
+
+impl<T> MultiFormat<T> {
+    /// Creates a set with no formats, that fails on files it does not recognize.
+    pub fn new() -> MultiFormat<T> {
+        MultiFormat {
+            formats: Vec::new(),
+            unrecognized: Unrecognized::Error,
+        }
+    }
+
+    /// Adds format `S`, for the files `detector` recognizes.
+    pub fn format<S>(mut self, detector: Detector) -> MultiFormat<T>
+    where
+        S: StorableBytes + Into<T>,
+    {
+        let decode: Decode<T> = Box::new(|bytes| from_bytes::<S>(bytes).map(Into::into));
+        self.formats.push((detector, decode));
+        self
+    }
+
+    /// Sets whether files recognized by no format, or by more than one, are skipped or
+    /// fail the restore with `Error::RestoreError`.
+    pub fn unrecognized(mut self, unrecognized: Unrecognized) -> MultiFormat<T> {
+        self.unrecognized = unrecognized;
+        self
+    }
+
+    /// Restores the item of key `key` from `contents`, read from the file at `path`.
+    ///
+    /// Returns `None` if the file is to be skipped.
+    fn restore(&self, path: &Path, key: &str, contents: &[u8]) -> Result<Option<T>, Error> {
+        let mut matching = self.formats.iter().filter(|(detector, _)| detector.matches(key, contents));
+        let problem = match (matching.next(), matching.next()) {
+            (Some((_, decode)), None) => {
+                return decode(contents)
+                    .map(Some)
+                    .map_err(|e| Error::RestoreError(path.display().to_string(), e.0))
+            }
+            (None, _) => "no format recognizes this file",
+            (Some(_), Some(_)) => "more than one format recognizes this file",
+        };
+        match self.unrecognized {
+            Unrecognized::Skip => Ok(None),
+            Unrecognized::Error => Err(Error::RestoreError(path.display().to_string(), problem.to_string())),
+        }
+    }
+}
+
+impl<T> DirStorage<T> {
+    /// Tries to create a new `DirStorage` from a directory like `restore_with_options`,
+    /// restoring each file in the format of `formats` that recognizes it.
+    ///
+    /// Formats see the contents of the files without the `Options::framing` around them.
+    pub fn restore_multi_format(
+        path_str: &str,
+        options: Options,
+        formats: &MultiFormat<T>,
+    ) -> Result<DirStorage<T>, Error> {
+        let mut storage = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        for item_file in ItemFiles::new(Path::new(path_str), &options)? {
+            let (key, file_path) = item_file?;
+            let (mut reader, len) = open_item(&file_path, &options.framing)?;
+            let mut contents = Vec::new();
+            reader.read_to_end(&mut contents)?;
+            stats.files_read += 1;
+            stats.bytes_read += len;
+            if let Some(object) = formats.restore(&file_path, &key, &contents)? {
+                storage.insert(key, object);
+            }
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
+}
@@ -0,0 +1,148 @@
+//! File names derived from a hash of the keys, along with the manifest mapping them back.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::{lock_file, rename_staged, stage_file, Error};
+
+/// Hidden file of a directory mapping the file names made by a `NameHasher` to keys.
+pub(crate) const MANIFEST_FILE: &str = ".soter_manifest";
+
+/// Hidden file of a directory locked while its manifest is updated.
+pub(crate) const MANIFEST_LOCK_FILE: &str = ".soter_manifest.lock";
+
+/// How file names are computed from keys, when they are not the keys themselves
+///
+/// The same key always gives the same file name, so items can be found without reading
+/// anything else. Since keys cannot be recovered from their hash, every name given to a
+/// new key is also recorded in a hidden manifest file, `.soter_manifest`, which
+/// restoring reads to tell the key of each file. The manifest is updated before the
+/// files are written, once for all the new keys of a store, so a crash never leaves a
+/// file whose key is unknown.
+///
+/// Two keys hashing to the same name fail with `Error::KeyCollision`. The manifest is
+/// updated while holding the lock of a hidden `.soter_manifest.lock` file, and replaced
+/// atomically, so processes storing new keys to the same directory at the same time
+/// keep each other's entries.
+#[derive(Clone, Copy)]
+pub struct NameHasher {
+    hash: fn(&str) -> String,
+}
+
+impl fmt::Debug for NameHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("NameHasher")
+    }
+}
+
+impl PartialEq for NameHasher {
+    fn eq(&self, other: &NameHasher) -> bool {
+        std::ptr::fn_addr_eq(self.hash, other.hash)
+    }
+}
+
+impl Eq for NameHasher {}
+
+impl Default for NameHasher {
+    fn default() -> NameHasher {
+        NameHasher::new(fnv1a)
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of `key`, as 16 hexadecimal digits.
+fn fnv1a(key: &str) -> String {
+    let hash = key
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+impl NameHasher {
+    /// Creates a hasher naming the file of each key `hash(key)`.
+    ///
+    /// The names returned must be valid file names that do not start with `.`, and
+    /// must not contain nul bytes. `NameHasher::default()` uses the 64-bit FNV-1a hash,
+    /// which is fast but easy to reverse for short keys; keys that must stay private
+    /// need a cryptographic hash instead.
+    pub fn new(hash: fn(&str) -> String) -> NameHasher {
+        NameHasher { hash }
+    }
+
+    /// Returns the name of the file holding the item of key `key`.
+    pub fn hash(&self, key: &str) -> String {
+        (self.hash)(key)
+    }
+}
+
+/// Reads the manifest of directory `dir_path`, mapping file names to keys.
+///
+/// A directory without a manifest has an empty one.
+pub(crate) fn load_manifest(dir_path: &Path) -> Result<HashMap<String, String>, Error> {
+    let bytes = match fs::read(dir_path.join(MANIFEST_FILE)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let corrupt = || Error::RestoreError(MANIFEST_FILE.to_string(), "corrupt manifest".to_string());
+    let contents = String::from_utf8(bytes).map_err(|_| corrupt())?;
+    let mut fields = contents.split_terminator('\0');
+    let mut manifest = HashMap::new();
+    while let Some(name) = fields.next() {
+        let key = fields.next().ok_or_else(corrupt)?;
+        manifest.insert(name.to_string(), key.to_string());
+    }
+    Ok(manifest)
+}
+
+/// Changes the manifest of directory `dir_path` with `update`, while holding its lock.
+///
+/// The manifest is rewritten atomically, and only if `update` returns true.
+pub(crate) fn update_manifest<F>(dir_path: &Path, update: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut HashMap<String, String>) -> Result<bool, Error>,
+{
+    let _lock = lock_file(&dir_path.join(MANIFEST_LOCK_FILE))?;
+    let mut manifest = load_manifest(dir_path)?;
+    if !update(&mut manifest)? {
+        return Ok(());
+    }
+
+    let path = dir_path.join(MANIFEST_FILE);
+    let (tmp_path, _) = stage_file(&path, None, |mut writer| {
+        manifest
+            .iter()
+            .try_for_each(|(name, key)| write!(writer, "{}\0{}\0", name, key))
+            .and_then(|()| writer.flush())
+            .map_err(|e| crate::storable::StorableStoreError(e.to_string()))
+    })?;
+    rename_staged(&tmp_path, &path)
+}
+
+/// Records in the manifest of directory `dir_path` that each file named `name` holds
+/// the item of key `key`, for all the pairs of `names`.
+///
+/// The manifest is only rewritten if some of the names are new, and is left untouched
+/// if any of them fails.
+pub(crate) fn record_names<'k, I>(dir_path: &Path, names: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (String, &'k str)>,
+{
+    update_manifest(dir_path, |manifest| {
+        let mut changed = false;
+        for (name, key) in names {
+            match manifest.get(&name) {
+                Some(recorded) if recorded == key => continue,
+                Some(recorded) => return Err(Error::KeyCollision(format!("{} ({} and {})", name, recorded, key))),
+                None => {}
+            }
+            if key.contains('\0') {
+                return Err(Error::StoreError(key.to_string(), "keys cannot contain nul bytes".to_string()));
+            }
+            manifest.insert(name, key.to_string());
+            changed = true;
+        }
+        Ok(changed)
+    })
+}
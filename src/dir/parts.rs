@@ -0,0 +1,286 @@
+//! Items split across several files, to read and write them in parallel.
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{rename_staged, stage_file, DirStorage, Error, StoreEvent};
+use crate::storable::*;
+
+/// Length of the header starting every part file: its index and the part count as
+/// little-endian `u32`s, then the stamp of the store as a little-endian `u64`.
+const HEADER_LEN: usize = 16;
+
+/// Returns the path of part `index` of the item whose file would be `item_path`.
+fn part_path(item_path: &Path, index: usize) -> PathBuf {
+    let name = item_path.file_name().unwrap_or_default().to_string_lossy();
+    item_path.with_file_name(format!(".{}.part{}", name, index))
+}
+
+/// Lists the part files of the item whose file would be `item_path`, with their index.
+fn list_parts(item_path: &Path) -> Result<Vec<(usize, PathBuf)>, Error> {
+    let name = item_path.file_name().unwrap_or_default().to_string_lossy();
+    let prefix = format!(".{}.part", name);
+    let mut parts = Vec::new();
+    for entry in fs::read_dir(item_path.parent().unwrap_or_else(|| Path::new(".")))? {
+        let entry = entry?;
+        let index = entry
+            .file_name()
+            .to_str()
+            .and_then(|filename| filename.strip_prefix(&prefix))
+            .filter(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|index| index.parse().ok());
+        if let Some(index) = index {
+            parts.push((index, entry.path()));
+        }
+    }
+    Ok(parts)
+}
+
+/// Splits the name of a part file into the name of the item file it is a part of and
+/// its index, if it is one.
+fn part_of(filename: &str) -> Option<(&str, usize)> {
+    let (name, index) = filename.strip_prefix('.')?.rsplit_once(".part")?;
+    if name.is_empty() || index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, index.parse().ok()?))
+}
+
+/// Reads the index, the part count and the stamp at the start of the part file at
+/// `path`, if it has a whole header.
+fn read_header(path: &Path) -> Result<Option<(usize, usize, u64)>, Error> {
+    let mut header = [0; HEADER_LEN];
+    match File::open(path)?.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let index = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let count = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    let stamp = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    Ok(Some((index, count, stamp)))
+}
+
+/// Lists the part files of directory `dir_path` that `DirStorage::restore_parts` would
+/// fail on, because the `store_parts` that wrote them was interrupted, sorted.
+///
+/// All the parts of an item are listed as soon as one of them is corrupt, missing,
+/// beyond the count of the others, or from another store, since none of them can be
+/// restored anymore.
+pub(crate) fn stale_parts(dir_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut items: HashMap<String, Vec<(usize, PathBuf)>> = HashMap::new();
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        if let Some((name, index)) = part_of(&filename) {
+            if entry.file_type()?.is_file() {
+                items.entry(name.to_string()).or_default().push((index, entry.path()));
+            }
+        }
+    }
+
+    let mut stale = Vec::new();
+    for (_, parts) in items {
+        let mut stores = HashSet::new();
+        let mut indexes = HashSet::new();
+        let mut corrupt = false;
+        for (index, path) in &parts {
+            match read_header(path)? {
+                Some((stored_index, count, stamp)) if stored_index == *index => {
+                    stores.insert((count, stamp));
+                    indexes.insert(*index);
+                }
+                _ => corrupt = true,
+            }
+        }
+        // Distinct indexes, as many as the count and all below it, are all the parts.
+        let complete = match stores.iter().next() {
+            Some(&(count, _)) if !corrupt && stores.len() == 1 => {
+                parts.len() == count && indexes.len() == count && indexes.iter().all(|&index| index < count)
+            }
+            _ => false,
+        };
+        if !complete {
+            stale.extend(parts.into_iter().map(|(_, path)| path));
+        }
+    }
+    stale.sort();
+    Ok(stale)
+}
+
+/// Runs `f` on every element of `inputs`, spread over at most as many threads as the
+/// machine runs in parallel, and returns the results in order.
+fn in_parallel<I, O, F>(inputs: Vec<I>, f: F) -> Vec<O>
+where
+    I: Send,
+    O: Send,
+    F: Fn(I) -> O + Sync,
+{
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let chunk_len = inputs.len().div_ceil(threads).max(1);
+    let mut chunks = Vec::new();
+    let mut inputs = inputs.into_iter().peekable();
+    while inputs.peek().is_some() {
+        chunks.push(inputs.by_ref().take(chunk_len).collect::<Vec<I>>());
+    }
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| scope.spawn(move || chunk.into_iter().map(f).collect::<Vec<O>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+impl<T: StorableBytes> DirStorage<T> {
+    /// Stores the item of key `key` in directory `dir_path_str` as `parts` files,
+    /// written at the same time from as many threads as the machine runs in parallel.
+    ///
+    /// The bytes of the item are split into parts of about the same size, each stored
+    /// in the hidden file `.<name>.part<index>` next to where the item file would be,
+    /// counting from 0, and the parts of a previous store beyond the new count are
+    /// removed. Each part is written atomically, but the parts as a whole are not:
+    /// `restore_parts` detects the parts of stores that were interrupted, and fails,
+    /// while `recover` and `purge_orphans` remove them. Part files are not framed, and
+    /// whole-directory restores ignore them. A `parts` of 0 fails with
+    /// `Error::StoreError`.
+    pub fn store_parts<D, S>(&self, dir_path_str: D, key: S, parts: usize) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+    {
+        let key = key.as_ref();
+        let item_path = self.options.path_of(Path::new(dir_path_str.as_ref()), key);
+        let result = match parts {
+            0 => Err(Error::StoreError(item_path.display().to_string(), "an item needs at least one part".to_string())),
+            _ => self.write_parts(&item_path, key, parts),
+        };
+        self.count_error(result)?;
+        self.emit(StoreEvent::Stored { key: String::from(key) });
+        Ok(())
+    }
+
+    fn write_parts(&self, item_path: &Path, key: &str, parts: usize) -> Result<(), Error> {
+        let storable = self.storage.get(key).ok_or_else(|| Error::NotFound(String::from(key)))?;
+        let bytes = to_bytes(storable).map_err(|e| Error::StoreError(item_path.display().to_string(), e.0))?;
+        if let Some(parent) = item_path.parent().filter(|_| !self.options.shards.is_flat()) {
+            fs::create_dir_all(parent)?;
+        }
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        let part_len = bytes.len().div_ceil(parts).max(1);
+        let inputs = (0..parts)
+            .map(|index| {
+                let start = (index * part_len).min(bytes.len());
+                let end = (start + part_len).min(bytes.len());
+                (index, &bytes[start..end])
+            })
+            .collect();
+        let written = in_parallel(inputs, |(index, part)| -> Result<u64, Error> {
+            let path = part_path(item_path, index);
+            let (tmp_path, len) = stage_file(&path, None, |mut writer| {
+                writer
+                    .write_all(&(index as u32).to_le_bytes())
+                    .and_then(|()| writer.write_all(&(parts as u32).to_le_bytes()))
+                    .and_then(|()| writer.write_all(&stamp.to_le_bytes()))
+                    .and_then(|()| writer.write_all(part))
+                    .and_then(|()| writer.flush())
+                    .map_err(|e| StorableStoreError(e.to_string()))
+            })?;
+            rename_staged(&tmp_path, &path)?;
+            Ok(len)
+        });
+        for len in written {
+            let len = len?;
+            self.update_stats(|stats| {
+                stats.files_written += 1;
+                stats.bytes_written += len;
+            });
+        }
+
+        for (index, path) in list_parts(item_path)? {
+            if index >= parts {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the item of key `key` from the part files written to directory
+    /// `dir_path_str` by `store_parts`, read at the same time from different threads
+    /// like they are written, replacing any item with that key in memory.
+    ///
+    /// Returns `false`, and leaves memory untouched, if there are no part files for
+    /// that key. Missing parts, parts beyond the count they were stored with, and parts
+    /// from different stores fail with `Error::RestoreError`.
+    pub fn restore_parts<D, S>(&mut self, dir_path_str: D, key: S) -> Result<bool, Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+    {
+        let key = key.as_ref();
+        let item_path = self.options.path_of(Path::new(dir_path_str.as_ref()), key);
+        let result = self.read_parts(&item_path);
+        match self.count_error(result)? {
+            Some(object) => {
+                self.storage.insert(String::from(key), object);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn read_parts(&self, item_path: &Path) -> Result<Option<T>, Error> {
+        let listed = list_parts(item_path)?;
+        if listed.is_empty() {
+            return Ok(None);
+        }
+        let invalid = |what: String| Error::RestoreError(item_path.display().to_string(), what);
+
+        let read = in_parallel(listed, |(index, path)| fs::read(&path).map(|bytes| (index, bytes)));
+        let mut parts = HashMap::new();
+        let mut header = None;
+        for result in read {
+            let (index, bytes) = result?;
+            self.update_stats(|stats| {
+                stats.files_read += 1;
+                stats.bytes_read += bytes.len() as u64;
+            });
+            if bytes.len() < HEADER_LEN || u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize != index {
+                return Err(invalid(format!("part {} is corrupt", index)));
+            }
+            let count = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+            let stamp = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+            match header {
+                None => header = Some((count, stamp)),
+                Some(first) if first != (count, stamp) => {
+                    return Err(invalid("parts are from different stores".to_string()))
+                }
+                Some(_) => {}
+            }
+            parts.insert(index, bytes);
+        }
+
+        let (count, _) = header.expect("there is at least one part");
+        if let Some(extra) = parts.keys().filter(|&&index| index >= count).min() {
+            return Err(invalid(format!("unexpected part {} of {}", extra, count)));
+        }
+        let mut bytes = Vec::new();
+        for index in 0..count {
+            let part = parts.get(&index).ok_or_else(|| invalid(format!("missing part {} of {}", index, count)))?;
+            bytes.extend_from_slice(&part[HEADER_LEN..]);
+        }
+        from_bytes(&bytes).map(Some).map_err(|e| invalid(e.0))
+    }
+}
@@ -0,0 +1,145 @@
+//! Restoring directories along with their subdirectories.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{read_file, BufReadFile, BufWriteFile, DirStorage, Error, ItemFiles, Options, StoreStats};
+use crate::storable::*;
+
+/// How deep `DirStorage::restore_recursive` descends into subdirectories
+///
+/// Recursions start from their defaults with `Recursion::default()`, which descends up
+/// to 32 levels of subdirectories and skips whatever is deeper.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Recursion {
+    max_depth: usize,
+    fail_too_deep: bool,
+}
+
+impl Default for Recursion {
+    fn default() -> Recursion {
+        Recursion {
+            max_depth: 32,
+            fail_too_deep: false,
+        }
+    }
+}
+
+impl Recursion {
+    /// Sets how many levels of subdirectories are descended into. With 0, only the files
+    /// of the directory itself are restored, like `restore_with_options` does. 32 by
+    /// default.
+    pub fn max_depth(mut self, max_depth: usize) -> Recursion {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets whether subdirectories deeper than the maximum depth fail the restore with
+    /// `Error::RestoreError`, instead of being skipped. Disabled by default.
+    pub fn fail_too_deep(mut self, fail_too_deep: bool) -> Recursion {
+        self.fail_too_deep = fail_too_deep;
+        self
+    }
+}
+
+/// A directory left to restore, with the prefix of the keys of its items.
+struct Pending {
+    path: PathBuf,
+    prefix: String,
+    depth: usize,
+    ancestors: Vec<PathBuf>,
+}
+
+/// Lists the subdirectories of `path` that are not hidden, following symbolic links,
+/// along with their names.
+fn subdirectories(path: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut subdirectories = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+        let subdirectory = entry.path();
+        if subdirectory.is_dir() {
+            subdirectories.push((name, subdirectory));
+        }
+    }
+    Ok(subdirectories)
+}
+
+impl<T> DirStorage<T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
+    /// Tries to create a new `DirStorage` from a directory and its subdirectories, like
+    /// `restore_with_options` does for the directory alone.
+    ///
+    /// The key of an item is the path of its file relative to `path_str`, with `/`
+    /// between its components whatever the platform. Each component is decoded on its
+    /// own following `Options::key_encoding`, so a key decoded from an escaped `/` cannot
+    /// be told apart from a key with one more directory. With `Options::name_hasher`,
+    /// directory names are not hashed, and each directory has its own manifest for the
+    /// names of its files. Storing items back under such keys needs their directories
+    /// to exist already.
+    ///
+    /// Hidden subdirectories, such as the backups of `Options::backups`, are skipped.
+    /// Subdirectories deeper than `recursion` allows are skipped or fail the restore,
+    /// and a symbolic link to one of the directories it is in is always skipped, so
+    /// that restoring untrusted trees terminates. Subdirectories cannot be told apart
+    /// from shards, so sharded `Options::shards` fail with `Error::Unsupported`.
+    pub fn restore_recursive(path_str: &str, options: Options, recursion: Recursion) -> Result<DirStorage<T>, Error> {
+        if !options.shards.is_flat() {
+            return Err(Error::Unsupported("restore_recursive with shards".to_string()));
+        }
+        let mut storage = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        let mut pending = vec![Pending {
+            path: PathBuf::from(path_str),
+            prefix: String::new(),
+            depth: 0,
+            ancestors: Vec::new(),
+        }];
+        while let Some(dir) = pending.pop() {
+            for item_file in ItemFiles::new(&dir.path, &options)? {
+                let (key, file_path) = item_file?;
+                let (object, len) = read_file(&file_path, &options)?;
+                stats.files_read += 1;
+                stats.bytes_read += len;
+                storage.insert(format!("{}{}", dir.prefix, key), object);
+            }
+            if !dir.path.is_dir() {
+                continue;
+            }
+
+            let mut ancestors = dir.ancestors;
+            ancestors.push(fs::canonicalize(&dir.path)?);
+            for (name, subdirectory) in subdirectories(&dir.path)? {
+                if ancestors.contains(&fs::canonicalize(&subdirectory)?) {
+                    continue;
+                }
+                if dir.depth == recursion.max_depth {
+                    if recursion.fail_too_deep {
+                        return Err(Error::RestoreError(
+                            subdirectory.display().to_string(),
+                            format!("deeper than the maximum depth of {}", recursion.max_depth),
+                        ));
+                    }
+                    continue;
+                }
+                pending.push(Pending {
+                    path: subdirectory,
+                    prefix: format!("{}{}/", dir.prefix, options.key_encoding.decode(&name)),
+                    depth: dir.depth + 1,
+                    ancestors: ancestors.clone(),
+                });
+            }
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
+}
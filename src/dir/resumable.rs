@@ -0,0 +1,85 @@
+//! Restoring a directory in several runs, resuming where the last one stopped.
+use std::collections::HashSet;
+use std::iter::FromIterator;
+use std::path::Path;
+
+use super::{read_file, BufReadFile, BufWriteFile, DirStorage, Error, ItemFiles};
+use crate::storable::*;
+
+/// The keys already restored by `DirStorage::restore_resumable`
+///
+/// A checkpoint is only a set of keys, so it can be saved by the caller with `keys` and
+/// rebuilt from them with `collect`, to resume a restore after the process stopped.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RestoreCheckpoint {
+    done: HashSet<String>,
+}
+
+impl RestoreCheckpoint {
+    /// Creates a checkpoint with no key restored yet.
+    pub fn new() -> RestoreCheckpoint {
+        RestoreCheckpoint::default()
+    }
+
+    /// Returns whether the item of key `key` was already restored.
+    pub fn is_done(&self, key: &str) -> bool {
+        self.done.contains(key)
+    }
+
+    /// Returns the number of items already restored.
+    pub fn len(&self) -> usize {
+        self.done.len()
+    }
+
+    /// Returns true if no item was restored yet.
+    pub fn is_empty(&self) -> bool {
+        self.done.is_empty()
+    }
+
+    /// Returns the keys of the items already restored, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.done.iter().map(String::as_str)
+    }
+}
+
+impl FromIterator<String> for RestoreCheckpoint {
+    fn from_iter<I: IntoIterator<Item = String>>(keys: I) -> RestoreCheckpoint {
+        RestoreCheckpoint {
+            done: keys.into_iter().collect(),
+        }
+    }
+}
+
+impl<T> DirStorage<T>
+where
+    T: Storable<BufWriteFile, BufReadFile>,
+{
+    /// Restores into this storage the items of directory `path_str` whose keys are not
+    /// in `checkpoint` yet, adding each key to `checkpoint` once its item is in memory.
+    ///
+    /// Files are found following the options of this storage, like
+    /// `restore_with_options`. The restore stops at the first file that fails, and the
+    /// items restored before it stay in memory and in `checkpoint`, so calling this
+    /// again with the same checkpoint, once the problem is fixed, only restores the
+    /// remaining files. If the process stops instead, the storage given to the next
+    /// call must already hold the items of the keys in `checkpoint`, for instance
+    /// because they were handed over somewhere else.
+    pub fn restore_resumable(&mut self, path_str: &str, checkpoint: &mut RestoreCheckpoint) -> Result<(), Error> {
+        for item_file in ItemFiles::new(Path::new(path_str), &self.options)? {
+            let (key, file_path) = self.count_error(item_file)?;
+            if checkpoint.is_done(&key) {
+                continue;
+            }
+            let result = read_file(&file_path, &self.options);
+            let (object, len) = self.count_error(result)?;
+            self.update_stats(|stats| {
+                stats.files_read += 1;
+                stats.bytes_read += len;
+            });
+            self.storage.insert(key.clone(), object);
+            checkpoint.done.insert(key);
+        }
+        self.update_stats(|stats| stats.restores += 1);
+        Ok(())
+    }
+}
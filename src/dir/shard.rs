@@ -0,0 +1,169 @@
+//! Spreading item files over subdirectories, and moving them between sharded layouts.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{name_hasher, DirStorage, Error};
+
+/// How item files are spread over the subdirectories of a directory
+///
+/// A storage follows a scheme with `Options::shards`, which starts `Flat`. Shards are
+/// named after the file names of the items, so with `Options::key_encoding` after the
+/// encoded keys, and with `Options::name_hasher` after their hashes.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ShardScheme {
+    /// Every file is directly in the directory.
+    #[default]
+    Flat,
+    /// Every file is in a subdirectory named after the first characters of its name,
+    /// as many as given, or all of them for shorter names. `Prefix(0)` is the same as
+    /// `Flat`.
+    Prefix(usize),
+}
+
+impl ShardScheme {
+    /// Returns the path of the file named `name`, under directory `dir_path`.
+    pub fn path_for(&self, dir_path: &Path, name: &str) -> PathBuf {
+        match *self {
+            ShardScheme::Prefix(len) if len > 0 => {
+                let shard: String = name.chars().take(len).collect();
+                dir_path.join(shard).join(name)
+            }
+            _ => dir_path.join(name),
+        }
+    }
+
+    /// Returns whether every file is directly in the directory.
+    pub(crate) fn is_flat(&self) -> bool {
+        self.path_for(Path::new(""), "name") == Path::new("name")
+    }
+}
+
+/// A file moved by `DirStorage::rebalance_shards`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ShardMove {
+    /// The key of the item held by the file.
+    pub key: String,
+    /// Where the file was.
+    pub from: PathBuf,
+    /// Where the file goes under the new scheme.
+    pub to: PathBuf,
+}
+
+/// Suffix of the hidden names `rebalance_shards` gives files while moving them.
+const REBALANCE_SUFFIX: &str = ".rebalance";
+
+/// Returns the name of the file that `filename`, a hidden name given by
+/// `rebalance_shards`, stands for, if it is one.
+pub(crate) fn rebalanced_name(filename: &str) -> Option<&str> {
+    filename
+        .strip_prefix('.')?
+        .strip_suffix(REBALANCE_SUFFIX)
+        .filter(|name| !name.is_empty())
+}
+
+/// Lists the subdirectories of `dir_path` that are not hidden, which hold the shards of a
+/// sharded layout.
+pub(crate) fn shard_dirs(dir_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut shards = Vec::new();
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => {}
+            _ => continue,
+        }
+        if entry.file_type()?.is_dir() {
+            shards.push(entry.path());
+        }
+    }
+    Ok(shards)
+}
+
+/// Collects the item files found in `dir_path` and its subdirectories, by name.
+///
+/// Hidden files and directories are skipped, and symbolic links are not followed.
+fn find_items(dir_path: &Path, items: &mut HashMap<String, PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            find_items(&entry.path(), items)?;
+        } else if let Some(other) = items.insert(name.clone(), entry.path()) {
+            return Err(Error::KeyCollision(format!("{} ({} and {})", name, other.display(), entry.path().display())));
+        }
+    }
+    Ok(())
+}
+
+impl<T> DirStorage<T> {
+    /// Moves every item file of directory `dir_path_str` to where `scheme` puts it, and
+    /// returns the files moved, sorted by key.
+    ///
+    /// Files can be laid out following any scheme, or a mix of them, beforehand, and are
+    /// found by their names, whose keys are told following the options of this storage.
+    /// Once moved, they are found by storages following `Options::shards` with `scheme`.
+    /// If `dry_run` is true, the moves are only returned, and nothing is changed.
+    ///
+    /// Files are first renamed to hidden names in the directory, then to their new
+    /// places, so a file can take the place of a subdirectory and the other way
+    /// around. Files left under hidden names by a crash are put back by `recover`.
+    /// Subdirectories left empty are removed. Two files with the same name, in
+    /// different subdirectories, fail with `Error::KeyCollision` before anything is
+    /// moved.
+    pub fn rebalance_shards<D>(&self, dir_path_str: D, scheme: ShardScheme, dry_run: bool) -> Result<Vec<ShardMove>, Error>
+    where
+        D: AsRef<str>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let mut items = HashMap::new();
+        find_items(dir_path, &mut items)?;
+        let manifest = match self.options.name_hasher {
+            Some(_) => Some(name_hasher::load_manifest(dir_path)?),
+            None => None,
+        };
+        let mut found = Vec::new();
+        for (name, from) in items {
+            let to = scheme.path_for(dir_path, &name);
+            if from == to {
+                continue;
+            }
+            let key = match &manifest {
+                Some(manifest) => manifest.get(&name).cloned().ok_or_else(|| {
+                    Error::RestoreError(from.display().to_string(), "not in the manifest".to_string())
+                })?,
+                None => self.options.key_encoding.decode(&name).into_owned(),
+            };
+            found.push((ShardMove { key, from, to }, name));
+        }
+        found.sort_by(|(a, _), (b, _)| a.key.cmp(&b.key));
+        let (moves, names): (Vec<ShardMove>, Vec<String>) = found.into_iter().unzip();
+        if dry_run {
+            return Ok(moves);
+        }
+
+        let mut staged = Vec::with_capacity(moves.len());
+        for (shard_move, name) in moves.iter().zip(&names) {
+            let tmp_path = dir_path.join(format!(".{}{}", name, REBALANCE_SUFFIX));
+            fs::rename(&shard_move.from, &tmp_path)?;
+            staged.push(tmp_path);
+        }
+        for shard_move in &moves {
+            if let Some(parent) = shard_move.from.parent() {
+                if parent != dir_path {
+                    let _ = fs::remove_dir(parent);
+                }
+            }
+        }
+        for (shard_move, tmp_path) in moves.iter().zip(&staged) {
+            if let Some(parent) = shard_move.to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(tmp_path, &shard_move.to)?;
+        }
+        Ok(moves)
+    }
+}
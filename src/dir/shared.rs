@@ -0,0 +1,120 @@
+//! Restoring files with the same contents into one shared value.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::{read_file, BufReadFile, BufWriteFile, DirStorage, Error, ItemFiles, Options, StoreStats};
+use crate::storable::*;
+
+/// The items restored from files with the same hash and size, each with the first file
+/// it was restored from.
+type SameHash<U> = Vec<(PathBuf, Rc<U>)>;
+
+/// Returns a hash of the contents of the file at `path`, read a buffer at a time, along
+/// with its size.
+fn hash_file(path: &Path) -> Result<(u64, u64), Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = DefaultHasher::new();
+    let mut len = 0;
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok((hasher.finish(), len));
+        }
+        let read = chunk.len();
+        hasher.write(chunk);
+        len += read as u64;
+        reader.consume(read);
+    }
+}
+
+/// Returns whether the files at `a` and `b` hold the same bytes, reading them a buffer
+/// at a time.
+fn same_contents(a: &Path, b: &Path) -> Result<bool, Error> {
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    loop {
+        let (chunk_a, chunk_b) = (a.fill_buf()?, b.fill_buf()?);
+        let len = chunk_a.len().min(chunk_b.len());
+        if len == 0 {
+            return Ok(chunk_a.is_empty() && chunk_b.is_empty());
+        }
+        if chunk_a[..len] != chunk_b[..len] {
+            return Ok(false);
+        }
+        a.consume(len);
+        b.consume(len);
+    }
+}
+
+impl<U> DirStorage<Rc<U>>
+where
+    U: Storable<BufWriteFile, BufReadFile>,
+{
+    /// Tries to create a new `DirStorage` from a directory, restoring files with the
+    /// same contents only once.
+    ///
+    /// All the keys whose files hold the same bytes share a single `Rc`. Files are told
+    /// apart by a hash of their contents, and compared byte for byte with the file
+    /// already restored when their hashes match, so the contents of the files are never
+    /// all kept in memory. On Unix, hard links to a file already restored are
+    /// recognized by their inode without being read again. Otherwise this restores the
+    /// same items as `restore_with_options`.
+    pub fn restore_shared(path_str: &str, options: Options) -> Result<DirStorage<Rc<U>>, Error> {
+        let mut storage = HashMap::new();
+        let mut by_contents: HashMap<(u64, u64), SameHash<U>> = HashMap::new();
+        #[cfg(unix)]
+        let mut by_inode: HashMap<(u64, u64), Rc<U>> = HashMap::new();
+        let mut stats = StoreStats {
+            restores: 1,
+            ..StoreStats::default()
+        };
+        for item_file in ItemFiles::new(Path::new(path_str), &options)? {
+            let (key, file_path) = item_file?;
+
+            #[cfg(unix)]
+            let inode = {
+                use std::os::unix::fs::MetadataExt;
+
+                let metadata = fs::metadata(&file_path)?;
+                let inode = (metadata.dev(), metadata.ino());
+                if let Some(object) = by_inode.get(&inode) {
+                    storage.insert(key, Rc::clone(object));
+                    continue;
+                }
+                inode
+            };
+
+            let (hash, len) = hash_file(&file_path)?;
+            stats.files_read += 1;
+            stats.bytes_read += len;
+            let candidates = by_contents.entry((hash, len)).or_default();
+            let mut shared = None;
+            for (candidate_path, object) in candidates.iter() {
+                if same_contents(candidate_path, &file_path)? {
+                    shared = Some(Rc::clone(object));
+                    break;
+                }
+            }
+            let object = match shared {
+                Some(object) => object,
+                None => {
+                    let (object, _) = read_file(&file_path, &options)?;
+                    let object = Rc::new(object);
+                    candidates.push((file_path, Rc::clone(&object)));
+                    object
+                }
+            };
+            #[cfg(unix)]
+            by_inode.insert(inode, Rc::clone(&object));
+            storage.insert(key, object);
+        }
+        let dirstor = DirStorage::with_options(storage, options);
+        dirstor.stats.set(stats);
+        Ok(dirstor)
+    }
+}
@@ -0,0 +1,132 @@
+//! Streaming a whole `DirStorage` through a single reader or writer.
+//!
+//! The stream is a sequence of records, one per item. Each record is made of:
+//!
+//! * the length of the key, as a little-endian `u64`,
+//! * the key, in UTF-8,
+//! * the length of the value, as a little-endian `u64`,
+//! * the value, as written by its `Storable` implementation.
+//!
+//! The stream ends at the end of the last record.
+//!
+//! A single-file store, written by `DirStorage::store_to_single_file`, is a file holding
+//! such a stream.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use super::{rename_staged, stage_file, DirStorage, Error};
+use crate::adaptors::read_len_prefixed;
+use crate::storable::*;
+
+/// Writes one record of a stream.
+fn write_record<W: Write>(mut writer: W, key: &str, value: &[u8]) -> io::Result<()> {
+    writer.write_all(&(key.len() as u64).to_le_bytes())?;
+    writer.write_all(key.as_bytes())?;
+    writer.write_all(&(value.len() as u64).to_le_bytes())?;
+    writer.write_all(value)
+}
+
+/// Reads one record of a stream, returning `None` at the end of the stream.
+fn read_record<R: Read>(mut reader: R) -> Result<Option<(String, Vec<u8>)>, String> {
+    let key = match read_len_prefixed(&mut reader).map_err(|e| e.to_string())? {
+        Some(key) => String::from_utf8(key).map_err(|_| "key is not valid UTF-8".to_string())?,
+        None => return Ok(None),
+    };
+    let value = read_len_prefixed(&mut reader)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("{}: missing value", key))?;
+    Ok(Some((key, value)))
+}
+
+impl<T> DirStorage<T>
+where
+    T: StorableBytes,
+{
+    /// Writes every item to `writer`, as a stream of records.
+    ///
+    /// Records are written in key order, so exporting equal storages gives equal
+    /// streams. The stream can be read back with `load_stream`.
+    pub fn export<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut keys: Vec<&String> = self.storage.keys().collect();
+        keys.sort();
+        for key in keys {
+            let value = to_bytes(&self.storage[key]).map_err(|e| Error::StoreError(key.clone(), e.0))?;
+            write_record(&mut writer, key, &value)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Tries to create a new `DirStorage` from a stream written by `export`.
+    ///
+    /// If a record is malformed, or its value cannot be restored, an
+    /// `Error::RestoreError` naming the index of the record is returned.
+    pub fn load_stream<R: Read>(mut reader: R) -> Result<DirStorage<T>, Error> {
+        let mut storage = HashMap::new();
+        for index in 0.. {
+            let record_name = || format!("record {}", index);
+            let (key, value) = match read_record(&mut reader).map_err(|e| Error::RestoreError(record_name(), e))? {
+                Some(record) => record,
+                None => break,
+            };
+            let object = from_bytes(&value)
+                .map_err(|e| Error::RestoreError(format!("{} ({})", record_name(), key), e.0))?;
+            storage.insert(key, object);
+        }
+        Ok(DirStorage::new(storage))
+    }
+
+    /// Creates a new `DirStorage` from in-memory `(key, bytes)` pairs, such as
+    /// files embedded in the binary.
+    ///
+    /// Every entry is restored, whatever its key: the rules used to skip files
+    /// when restoring a directory do not apply.
+    pub fn restore_from_bytes<I>(entries: I) -> Result<DirStorage<T>, Error>
+    where
+        I: IntoIterator<Item = (String, Vec<u8>)>,
+    {
+        let mut storage = HashMap::new();
+        for (key, value) in entries {
+            let object = from_bytes(&value).map_err(|e| Error::RestoreError(key.clone(), e.0))?;
+            storage.insert(key, object);
+        }
+        Ok(DirStorage::new(storage))
+    }
+
+    /// Tries to store all the items in the single file at `path`, as a stream of
+    /// records.
+    ///
+    /// The file is replaced atomically, whatever `Options::atomic_writes` says, so a
+    /// crash never leaves a half-written store behind.
+    pub fn store_to_single_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let (tmp_path, len) = stage_file(path, None, |writer| {
+            self.export(writer).map_err(|e| StorableStoreError(e.to_string()))
+        })?;
+        rename_staged(&tmp_path, path)?;
+        self.update_stats(|stats| {
+            stats.stores += 1;
+            stats.files_written += 1;
+            stats.bytes_written += len;
+        });
+        Ok(())
+    }
+
+    /// Tries to create a new `DirStorage` from the single file at `path`, written by
+    /// `store_to_single_file`.
+    ///
+    /// If a record is damaged, an `Error::RestoreError` naming its index is returned.
+    pub fn restore_from_single_file<P: AsRef<Path>>(path: P) -> Result<DirStorage<T>, Error> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let dirstor = DirStorage::load_stream(BufReader::new(file))?;
+        dirstor.update_stats(|stats| {
+            stats.restores += 1;
+            stats.files_read += 1;
+            stats.bytes_read += len;
+        });
+        Ok(dirstor)
+    }
+}
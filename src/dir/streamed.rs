@@ -0,0 +1,64 @@
+//! Storing and restoring collection items one element at a time.
+use std::path::Path;
+
+use super::{open_item, write_file, BufReadFile, BufWriteFile, DirStorage, Error, StoreEvent};
+use crate::storable::*;
+
+impl<T> DirStorage<T>
+where
+    T: StorableStream<BufWriteFile, BufReadFile>,
+{
+    /// Stores the collection made of `items` as the item of key `key` in directory
+    /// `dir_path_str`, writing each element as soon as the iterator yields it.
+    ///
+    /// Nothing is kept in memory, not even the item of that key if there is one, so
+    /// the file can be made of more elements than would fit in memory. The file is
+    /// written following the options of this storage, like `store_single`.
+    pub fn store_single_stream<D, S, I>(&self, dir_path_str: D, key: S, items: I) -> Result<(), Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+        I: IntoIterator<Item = T::Item>,
+    {
+        let dir_path = Path::new(dir_path_str.as_ref());
+        let result = self.options.record_key(dir_path, key.as_ref()).and_then(|()| {
+            let path = self.options.path_of(dir_path, key.as_ref());
+            write_file(&path, key.as_ref(), &self.options, |writer| T::store_stream(items.into_iter(), writer))
+        });
+        let len = self.count_error(result)?;
+        self.update_stats(|stats| {
+            stats.files_written += 1;
+            stats.bytes_written += len;
+        });
+        self.emit(StoreEvent::Stored { key: String::from(key.as_ref()) });
+        Ok(())
+    }
+
+    /// Reads the item of key `key` from directory `dir_path_str`, passing each of its
+    /// elements to `sink` as soon as it is read.
+    ///
+    /// The item is not inserted in memory. Returns `false` if there is no such file, in
+    /// which case `sink` is never called.
+    pub fn restore_single_stream<D, S, F>(&self, dir_path_str: D, key: S, sink: F) -> Result<bool, Error>
+    where
+        D: AsRef<str>,
+        S: AsRef<str>,
+        F: FnMut(T::Item),
+    {
+        let path = self.options.path_of(Path::new(dir_path_str.as_ref()), key.as_ref());
+        if !path.is_file() {
+            return Ok(false);
+        }
+        let result = open_item(&path, &self.options.framing).and_then(|(reader, len)| {
+            T::restore_stream(reader, sink)
+                .map(|()| len)
+                .map_err(|e| Error::RestoreError(path.display().to_string(), e.0))
+        });
+        let len = self.count_error(result)?;
+        self.update_stats(|stats| {
+            stats.files_read += 1;
+            stats.bytes_read += len;
+        });
+        Ok(true)
+    }
+}
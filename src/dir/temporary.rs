@@ -0,0 +1,44 @@
+//! Stores kept in a temporary directory, removed once they are no longer needed.
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{DirStorage, Error};
+
+/// The temporary directory of a store created by `DirStorage::temporary`
+///
+/// The directory, and everything in it, is removed when the guard is dropped, including
+/// while unwinding from a panic.
+#[derive(Debug)]
+pub struct TempGuard {
+    dir: tempfile::TempDir,
+    path_str: String,
+}
+
+impl TempGuard {
+    /// Returns the path of the temporary directory.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Returns the path of the temporary directory, as taken by `DirStorage::store` and
+    /// `DirStorage::restore`.
+    pub fn path_str(&self) -> &str {
+        &self.path_str
+    }
+}
+
+impl<T> DirStorage<T> {
+    /// Creates an empty `DirStorage` along with a fresh temporary directory for it, that
+    /// is removed when the returned guard is dropped.
+    ///
+    /// The directory is created in the system's temporary directory, and fails with
+    /// `Error::OSError` if its path is not valid UTF-8.
+    pub fn temporary() -> Result<(DirStorage<T>, TempGuard), Error> {
+        let dir = tempfile::Builder::new().prefix("soter").tempdir()?;
+        let path_str = match dir.path().to_str() {
+            Some(path_str) => path_str.to_string(),
+            None => return Err(Error::OSError(format!("{}: not valid UTF-8", dir.path().display()))),
+        };
+        Ok((DirStorage::new(HashMap::new()), TempGuard { dir, path_str }))
+    }
+}
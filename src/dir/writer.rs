@@ -0,0 +1,76 @@
+//! The writer items are stored through, which does not lose the errors of its last flush.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+type FlushError = Arc<Mutex<Option<io::Error>>>;
+
+/// A buffered writer to the file of an item, given to `Storable::store`
+///
+/// Like `BufWriter`, it writes what is left in its buffer when it is dropped, but the
+/// error of that last write is not ignored: the store fails with `Error::IOError`.
+/// Calling `flush` before returning from `store` is therefore not needed.
+///
+/// It takes the place of the `BufWriter<File>` items used to be stored through, so
+/// `Storable` impls for `BufWriter<File>` no longer apply to `DirStorage`.
+#[derive(Debug)]
+pub struct FileWriter {
+    writer: BufWriter<File>,
+    flush_error: FlushError,
+}
+
+/// Gets the error of the flush done when a `FileWriter` is dropped.
+#[derive(Debug)]
+pub(crate) struct Flushed(FlushError);
+
+impl Flushed {
+    /// Returns the error the `FileWriter` got writing its buffer when dropped, if any.
+    ///
+    /// Must be called once the writer is dropped.
+    pub(crate) fn check(self) -> io::Result<()> {
+        let mut flush_error = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match flush_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FileWriter {
+    /// Creates a writer to `file`, along with what reports its last flush.
+    pub(crate) fn new(file: File) -> (FileWriter, Flushed) {
+        let flush_error = FlushError::default();
+        let writer = FileWriter {
+            writer: BufWriter::new(file),
+            flush_error: Arc::clone(&flush_error),
+        };
+        (writer, Flushed(flush_error))
+    }
+
+    /// Returns the file written to.
+    pub fn get_ref(&self) -> &File {
+        self.writer.get_ref()
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            *self.flush_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+        }
+    }
+}
@@ -2,5 +2,16 @@
 //!
 //! Right now the only provided method is storage as files in a file system.
 //! If you want to do this, you can use the `DirStorage` struct in the `dir` module.
+//! The `storage` module abstracts over storage methods that work one key at a time.
+//!
+//! `DirStorage` stores items through a `dir::FileWriter` rather than a `BufWriter<File>`,
+//! so that the error of its last flush is not lost, and restores them through a
+//! `BufReader<Take<File>>` rather than a `BufReader<File>`, so that the footer of
+//! `dir::Framing` is kept out of them. This breaks `Storable` impls written for
+//! `BufWriter<File>` or `BufReader<File>`, which need to be written for any `Write` and
+//! `Read` instead, like those of this crate, or for `FileWriter` and
+//! `BufReader<Take<File>>`.
+pub mod adaptors;
 pub mod dir;
 pub mod storable;
+pub mod storage;
@@ -1,6 +1,17 @@
 use std::error::Error as StdError;
 use std::fmt;
 use std::io::{Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+#[cfg(feature = "async")]
+mod asynch;
+mod stream;
+mod text;
+
+#[cfg(feature = "async")]
+pub use self::asynch::{AsyncStorable, Blocking};
+pub use self::stream::StorableStream;
 
 #[derive(Debug)]
 pub struct StorableStoreError(pub String);
@@ -28,6 +39,8 @@ impl StdError for StorableRestoreError {}
 ///
 /// Types implementing `Storable` are able to write themselves in a writer,
 /// or deserialize themselves from a reader.
+///
+/// Integers, `bool` and `String` are stored as plain text.
 pub trait Storable<W, R>
 where
     W: Write,
@@ -36,4 +49,121 @@ where
 {
     fn restore(reader: R) -> Result<Self, StorableRestoreError>;
     fn store(&self, writer: W) -> Result<(), StorableStoreError>;
+
+    /// Restores the item held by a file of directory `dir`.
+    ///
+    /// `DirStorage` restores files with this method, so that items can find the other
+    /// files they refer to, such as large blobs kept next to them. By default, `dir` is
+    /// ignored and `restore` is called.
+    fn restore_from_dir(reader: R, dir: &Path) -> Result<Self, StorableRestoreError> {
+        let _ = dir;
+        Self::restore(reader)
+    }
+
+    /// Stores the item in a file of directory `dir`.
+    ///
+    /// This is the counterpart of `restore_from_dir`, which `DirStorage` stores files
+    /// with. By default, `dir` is ignored and `store` is called.
+    fn store_in_dir(&self, writer: W, dir: &Path) -> Result<(), StorableStoreError> {
+        let _ = dir;
+        self.store(writer)
+    }
+
+    /// Returns the number of bytes `store` will write, if it is known up front.
+    ///
+    /// This is only a hint, used to pre-size files. It is `None` by default.
+    fn serialized_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A type that can be stored, given some context
+///
+/// This is the counterpart of `Storable` for types that need external state to
+/// write themselves or deserialize themselves, such as an interner or a schema
+/// registry. The context is handed to both `restore` and `store`.
+pub trait StorableWithCtx<Ctx, W, R>
+where
+    W: Write,
+    R: Read,
+    Self: Sized,
+{
+    fn restore(reader: R, ctx: &Ctx) -> Result<Self, StorableRestoreError>;
+    fn store(&self, writer: W, ctx: &Ctx) -> Result<(), StorableStoreError>;
+}
+
+/// A type that can be stored in memory
+///
+/// This is implemented for every type that is `Storable` with a `Vec<u8>` writer and
+/// a byte slice reader, which includes any `Storable` implementation that is generic
+/// over its writer and reader. Adaptors rely on it to serialize the value they wrap.
+pub trait StorableBytes: for<'a, 'b> Storable<&'a mut Vec<u8>, &'a mut &'b [u8]> {}
+
+impl<T> StorableBytes for T where T: for<'a, 'b> Storable<&'a mut Vec<u8>, &'a mut &'b [u8]> {}
+
+/// Serializes `storable` into a new buffer.
+pub fn to_bytes<S: StorableBytes>(storable: &S) -> Result<Vec<u8>, StorableStoreError> {
+    let mut bytes = Vec::new();
+    storable.store(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Restores an instance of `S` from `bytes`.
+pub fn from_bytes<S: StorableBytes>(mut bytes: &[u8]) -> Result<S, StorableRestoreError> {
+    S::restore(&mut bytes)
+}
+
+/// Returns whether `a` and `b` are serialized into the same bytes.
+///
+/// Unlike `PartialEq`, this ignores whatever `store` does not write, such as caches
+/// kept alongside a value. Two values that fail to be serialized are never equal.
+pub fn storable_eq<S: StorableBytes>(a: &S, b: &S) -> bool {
+    match (to_bytes(a), to_bytes(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Stores nothing, so that a `DirStorage<()>` is a set of keys kept as empty files.
+///
+/// The contents of a file are ignored when restoring `()`, without even being read.
+impl<W, R> Storable<W, R> for ()
+where
+    W: Write,
+    R: Read,
+{
+    fn restore(_reader: R) -> Result<Self, StorableRestoreError> {
+        Ok(())
+    }
+
+    fn store(&self, _writer: W) -> Result<(), StorableStoreError> {
+        Ok(())
+    }
+
+    fn serialized_len(&self) -> Option<u64> {
+        Some(0)
+    }
+}
+
+/// Stores the shared value, and restores it into a new `Rc`.
+///
+/// Sharing is not preserved across a round-trip: two `Rc`s pointing to the same value
+/// are restored as two separate values.
+impl<T, W, R> Storable<W, R> for Rc<T>
+where
+    T: Storable<W, R>,
+    W: Write,
+    R: Read,
+{
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        T::restore(reader).map(Rc::new)
+    }
+
+    fn store(&self, writer: W) -> Result<(), StorableStoreError> {
+        T::store(self, writer)
+    }
+
+    fn serialized_len(&self) -> Option<u64> {
+        T::serialized_len(self)
+    }
 }
@@ -0,0 +1,64 @@
+//! Storing values through asynchronous readers and writers.
+use std::future::Future;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::task;
+
+use super::*;
+
+/// A type that can be stored through asynchronous I/O
+///
+/// This is the asynchronous counterpart of `Storable`. Unlike a blocking writer, an
+/// asynchronous writer cannot write what it buffered when it is dropped, so `store` is
+/// given the writer by reference, and its caller flushes it afterwards.
+///
+/// Types that only implement `Storable` can be wrapped in `Blocking` instead.
+pub trait AsyncStorable<W, R>: Sized
+where
+    W: AsyncWrite + Unpin + Send,
+    R: AsyncRead + Unpin + Send,
+{
+    /// Restores an instance of `Self` from `reader`.
+    fn restore(reader: R) -> impl Future<Output = Result<Self, StorableRestoreError>> + Send;
+
+    /// Writes `self` to `writer`.
+    fn store(&self, writer: &mut W) -> impl Future<Output = Result<(), StorableStoreError>> + Send;
+}
+
+/// Wrapper using the `Storable` implementation of a value through asynchronous I/O
+///
+/// The whole file is read, or written, asynchronously, and the value is converted
+/// from or to bytes in memory. The conversion is done on a thread where blocking is
+/// allowed, so a slow `Storable::restore` or `Storable::store` does not stall other
+/// tasks. Storing hands that thread a clone of the value.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Blocking<T>(pub T);
+
+impl<T, W, R> AsyncStorable<W, R> for Blocking<T>
+where
+    T: StorableBytes + Clone + Send + Sync + 'static,
+    W: AsyncWrite + Unpin + Send,
+    R: AsyncRead + Unpin + Send,
+{
+    async fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        task::spawn_blocking(move || from_bytes(&bytes).map(Blocking))
+            .await
+            .map_err(|e| StorableRestoreError(e.to_string()))?
+    }
+
+    async fn store(&self, writer: &mut W) -> Result<(), StorableStoreError> {
+        let value = self.0.clone();
+        let bytes = task::spawn_blocking(move || to_bytes(&value))
+            .await
+            .map_err(|e| StorableStoreError(e.to_string()))??;
+        writer
+            .write_all(&bytes)
+            .await
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
@@ -0,0 +1,28 @@
+//! Storing collections one element at a time.
+use super::*;
+
+/// A collection that can be stored from an iterator of its elements, and restored one
+/// element at a time, without ever being whole in memory
+///
+/// The `Storable` implementation of the collection must use the same format, so that a
+/// file written by one can be read by the other. `Records` implements this for a list
+/// of `StorableBytes` elements.
+pub trait StorableStream<W, R>: Storable<W, R>
+where
+    W: Write,
+    R: Read,
+{
+    /// The type of the elements of the collection.
+    type Item;
+
+    /// Writes the collection made of `items` to `writer`, as `store` would write it.
+    fn store_stream<I>(items: I, writer: W) -> Result<(), StorableStoreError>
+    where
+        I: Iterator<Item = Self::Item>;
+
+    /// Reads the collection written in `reader`, passing each of its elements to `sink`
+    /// as soon as it is read.
+    fn restore_stream<F>(reader: R, sink: F) -> Result<(), StorableRestoreError>
+    where
+        F: FnMut(Self::Item);
+}
@@ -0,0 +1,50 @@
+//! Storing integers, `bool` and `String` as plain text.
+use super::*;
+
+macro_rules! impl_storable_as_text {
+    ($($t:ty),*) => {
+        $(
+            impl<W, R> Storable<W, R> for $t
+            where
+                W: Write,
+                R: Read,
+            {
+                fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+                    let mut s = String::new();
+                    reader
+                        .read_to_string(&mut s)
+                        .map_err(|e| StorableRestoreError(e.to_string()))?;
+                    s.trim().parse().map_err(|_| {
+                        StorableRestoreError(format!("invalid {}: {}", stringify!($t), s.trim()))
+                    })
+                }
+
+                fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+                    write!(writer, "{}", self).map_err(|e| StorableStoreError(e.to_string()))
+                }
+            }
+        )*
+    };
+}
+
+impl_storable_as_text!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool);
+
+impl<W, R> Storable<W, R> for String
+where
+    W: Write,
+    R: Read,
+{
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut s = String::new();
+        reader
+            .read_to_string(&mut s)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        Ok(s)
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        writer
+            .write_all(self.as_bytes())
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
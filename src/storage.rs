@@ -0,0 +1,171 @@
+//! Backends that persist items one key at a time
+//!
+//! The `Storage` trait abstracts over where items are kept, so that code such as the
+//! `Cache`, `Tiered` and `Versioned` decorators can work on top of any backend.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::dir::Error;
+
+mod cache;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod tiered;
+mod versioned;
+
+pub use self::cache::{Cache, WriteMode};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStorage;
+pub use self::tiered::{TierWrites, Tiered};
+pub use self::versioned::Versioned;
+
+/// A change made by `Storage::batch`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BatchOp<T> {
+    /// Persists the value under the key, replacing any previous item.
+    Put(String, T),
+    /// Removes the item persisted under the key, if any.
+    Delete(String),
+}
+
+/// What a `Storage` guarantees, returned by `Storage::capabilities`
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Whether `Storage::batch` applies either all of its changes or none of them.
+    pub atomic_batch: bool,
+}
+
+/// A point-in-time copy of the items of a `Storage`, made by `Storage::snapshot`
+///
+/// The handle is the path of the copy, which only the backend that made it knows how
+/// to read. The copy is never removed by the backend, so it outlives the handle.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SnapshotHandle {
+    path: PathBuf,
+}
+
+impl SnapshotHandle {
+    /// Constructs a handle for the snapshot at `path`, such as one kept from an earlier
+    /// run.
+    pub fn new<P: Into<PathBuf>>(path: P) -> SnapshotHandle {
+        SnapshotHandle { path: path.into() }
+    }
+
+    /// Returns the path of the snapshot.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A backend that persists items of type `T` under string keys
+pub trait Storage<T> {
+    /// Returns the item persisted under `key`, if any.
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error>;
+
+    /// Persists `value` under `key`, replacing any previous item.
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error>;
+
+    /// Removes the item persisted under `key`, returning whether there was one.
+    fn delete(&mut self, key: &str) -> Result<bool, Error>;
+
+    /// Returns the keys of all persisted items, in no particular order.
+    fn keys(&mut self) -> Result<Vec<String>, Error>;
+
+    /// Returns whether an item is persisted under `key`.
+    ///
+    /// By default, the item is loaded, and dropped. Backends that can tell without
+    /// decoding the item do so instead.
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        self.load(key).map(|item| item.is_some())
+    }
+
+    /// Applies `ops` in order, as atomically as the backend allows.
+    ///
+    /// Backends whose `capabilities` have `atomic_batch` keep either all of the changes
+    /// or none of them. By default, the changes are made one by one with `save` and
+    /// `delete`, so a failure leaves the ones before it applied.
+    fn batch(&mut self, ops: Vec<BatchOp<T>>) -> Result<(), Error> {
+        for op in ops {
+            match op {
+                BatchOp::Put(key, value) => self.save(&key, &value)?,
+                BatchOp::Delete(key) => {
+                    self.delete(&key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns what this backend guarantees. By default, nothing.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Copies the items persisted right now, to roll back to them later with
+    /// `restore_snapshot`.
+    ///
+    /// By default, snapshots are not supported, and this fails with
+    /// `Error::Unsupported`.
+    fn snapshot(&self) -> Result<SnapshotHandle, Error> {
+        Err(Error::Unsupported("snapshot".to_string()))
+    }
+
+    /// Replaces all the persisted items with those of `snapshot`, made by `snapshot`.
+    ///
+    /// By default, snapshots are not supported, and this fails with
+    /// `Error::Unsupported`.
+    fn restore_snapshot(&mut self, snapshot: &SnapshotHandle) -> Result<(), Error> {
+        let _ = snapshot;
+        Err(Error::Unsupported("restore_snapshot".to_string()))
+    }
+}
+
+/// A `Storage` that keeps its items in memory
+///
+/// Nothing outlives the `MemStorage` itself, which makes it mostly useful in tests, or
+/// as a stand-in for a real backend.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MemStorage<T> {
+    items: HashMap<String, T>,
+}
+
+impl<T> Default for MemStorage<T> {
+    fn default() -> MemStorage<T> {
+        MemStorage::new(HashMap::new())
+    }
+}
+
+impl<T> MemStorage<T> {
+    /// Constructs a new `MemStorage` holding the items of `items`.
+    pub fn new(items: HashMap<String, T>) -> MemStorage<T> {
+        MemStorage { items }
+    }
+}
+
+impl<T: Clone> Storage<T> for MemStorage<T> {
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error> {
+        Ok(self.items.get(key).cloned())
+    }
+
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.items.insert(key.to_string(), value.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        Ok(self.items.remove(key).is_some())
+    }
+
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        Ok(self.items.keys().cloned().collect())
+    }
+
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        Ok(self.items.contains_key(key))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Changes made in memory cannot fail.
+        Capabilities { atomic_batch: true }
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{BatchOp, Capabilities, SnapshotHandle, Storage};
+use crate::dir::Error;
+
+/// When a `Cache` persists the changes made to it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WriteMode {
+    /// Every change is persisted to the backend before the call that makes it returns.
+    WriteThrough,
+    /// Changes are kept in memory until `flush` or `close` is called.
+    WriteBack,
+}
+
+/// A decorator that keeps the items of a `Storage` in memory
+///
+/// Items are loaded from the backend the first time they are asked for, and are then
+/// served from memory. Changes are persisted according to the `WriteMode`.
+///
+/// Only some of the items may be in memory at a time, so the cache has no `len`:
+/// `loaded_len` counts the items in memory, and `total_len` the items of the cache as a
+/// whole, including those only in the backend.
+///
+/// In `WriteMode::WriteBack`, pending changes are flushed when the cache is dropped,
+/// but errors can not be reported from `Drop`, so `close` should be used to shut a cache
+/// down cleanly.
+#[derive(Debug)]
+pub struct Cache<S, T>
+where
+    S: Storage<T>,
+{
+    backend: S,
+    mode: WriteMode,
+    items: HashMap<String, T>,
+    dirty: HashSet<String>,
+    removed: HashSet<String>,
+}
+
+impl<S, T> Cache<S, T>
+where
+    S: Storage<T>,
+{
+    /// Constructs a new, empty `Cache` in front of `backend`.
+    pub fn new(backend: S, mode: WriteMode) -> Cache<S, T> {
+        Cache {
+            backend,
+            mode,
+            items: HashMap::new(),
+            dirty: HashSet::new(),
+            removed: HashSet::new(),
+        }
+    }
+
+    /// Returns the backend this cache is in front of.
+    ///
+    /// In `WriteMode::WriteBack` the backend may not have the latest changes yet.
+    pub fn backend(&self) -> &S {
+        &self.backend
+    }
+
+    /// Returns the item associated with `key`, loading it from the backend if it is not
+    /// in memory yet.
+    pub fn get(&mut self, key: &str) -> Result<Option<&T>, Error> {
+        if !self.items.contains_key(key) && !self.removed.contains(key) {
+            if let Some(v) = self.backend.load(key)? {
+                self.items.insert(key.to_string(), v);
+            }
+        }
+        Ok(self.items.get(key))
+    }
+
+    /// Inserts a new item `v` associated with key `k`.
+    pub fn insert<K>(&mut self, k: K, v: T) -> Result<(), Error>
+    where
+        K: Into<String>,
+    {
+        let k = k.into();
+        match self.mode {
+            WriteMode::WriteThrough => self.backend.save(&k, &v)?,
+            WriteMode::WriteBack => {
+                self.removed.remove(&k);
+                self.dirty.insert(k.clone());
+            }
+        }
+        self.items.insert(k, v);
+        Ok(())
+    }
+
+    /// Removes the item associated with `key`, both from memory and from the backend.
+    pub fn remove(&mut self, key: &str) -> Result<(), Error> {
+        match self.mode {
+            WriteMode::WriteThrough => {
+                self.backend.delete(key)?;
+            }
+            WriteMode::WriteBack => {
+                self.dirty.remove(key);
+                self.removed.insert(key.to_string());
+            }
+        }
+        self.items.remove(key);
+        Ok(())
+    }
+
+    /// Returns the number of items in memory, which may be fewer than `total_len`.
+    pub fn loaded_len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns the items in memory along with their keys, in no particular order,
+    /// without loading any other item.
+    pub fn loaded(&self) -> impl Iterator<Item = (&String, &T)> {
+        self.items.iter()
+    }
+
+    /// Returns the number of items of the cache, whether they are in memory or only in
+    /// the backend, counting the changes that are not persisted yet.
+    ///
+    /// This lists the keys of the backend, but loads no item.
+    pub fn total_len(&mut self) -> Result<usize, Error>
+    where
+        T: Clone,
+    {
+        Storage::keys(self).map(|keys| keys.len())
+    }
+
+    /// Returns the keys with changes that are not persisted yet, whether their item was
+    /// inserted or removed, in no particular order.
+    ///
+    /// In `WriteMode::WriteThrough` there are none.
+    pub fn dirty_keys(&self) -> impl Iterator<Item = &String> {
+        self.dirty.iter().chain(self.removed.iter())
+    }
+
+    /// Returns true if `key` has a change that is not persisted yet.
+    pub fn is_dirty(&self, key: &str) -> bool {
+        self.dirty.contains(key) || self.removed.contains(key)
+    }
+
+    /// Persists every pending change to the backend.
+    ///
+    /// If persisting a change fails, the changes that were not persisted yet stay pending,
+    /// so `flush` can be retried.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let mut removed: Vec<String> = self.removed.iter().cloned().collect();
+        while let Some(key) = removed.pop() {
+            self.backend.delete(&key)?;
+            self.removed.remove(&key);
+        }
+
+        let mut dirty: Vec<String> = self.dirty.iter().cloned().collect();
+        while let Some(key) = dirty.pop() {
+            if let Some(v) = self.items.get(&key) {
+                self.backend.save(&key, v)?;
+            }
+            self.dirty.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Persists every pending change and drops the cache, reporting any error.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.flush()
+    }
+}
+
+impl<S, T> Drop for Cache<S, T>
+where
+    S: Storage<T>,
+{
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl<S, T> Storage<T> for Cache<S, T>
+where
+    S: Storage<T>,
+    T: Clone,
+{
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error> {
+        Ok(self.get(key)?.cloned())
+    }
+
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.insert(key, value.clone())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        let existed = self.get(key)?.is_some();
+        self.remove(key)?;
+        Ok(existed)
+    }
+
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        let mut keys: HashSet<String> = self.backend.keys()?.into_iter().collect();
+        keys.retain(|k| !self.removed.contains(k));
+        keys.extend(self.items.keys().cloned());
+        Ok(keys.into_iter().collect())
+    }
+
+    /// Returns whether `key` has an item, asking the backend if it is not in memory,
+    /// without loading it.
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        if self.items.contains_key(key) {
+            return Ok(true);
+        }
+        if self.removed.contains(key) {
+            return Ok(false);
+        }
+        self.backend.contains(key)
+    }
+
+    /// Applies `ops` to the backend in one batch, then drops the affected keys from
+    /// memory, so that they are loaded again from the backend.
+    ///
+    /// The batch replaces the changes not persisted yet to the same keys. If it fails,
+    /// those changes stay pending, and only the affected items without any are dropped,
+    /// since the backend may hold part of the batch.
+    fn batch(&mut self, ops: Vec<BatchOp<T>>) -> Result<(), Error> {
+        let keys: Vec<String> = ops
+            .iter()
+            .map(|op| match op {
+                BatchOp::Put(key, _) | BatchOp::Delete(key) => key.clone(),
+            })
+            .collect();
+        let result = self.backend.batch(ops);
+        for key in &keys {
+            if result.is_ok() {
+                self.dirty.remove(key);
+                self.removed.remove(key);
+            }
+            if !self.is_dirty(key) {
+                self.items.remove(key);
+            }
+        }
+        result
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.backend.capabilities()
+    }
+
+    /// Snapshots the backend.
+    ///
+    /// In `WriteMode::WriteBack`, the changes not persisted yet are not in the snapshot,
+    /// so `flush` should be called first.
+    fn snapshot(&self) -> Result<SnapshotHandle, Error> {
+        self.backend.snapshot()
+    }
+
+    /// Restores the backend to `snapshot`, dropping every item in memory along with the
+    /// changes not persisted yet.
+    fn restore_snapshot(&mut self, snapshot: &SnapshotHandle) -> Result<(), Error> {
+        self.backend.restore_snapshot(snapshot)?;
+        self.items.clear();
+        self.dirty.clear();
+        self.removed.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension, MAIN_DB};
+
+use super::{BatchOp, Capabilities, SnapshotHandle, Storage};
+use crate::dir::Error;
+use crate::storable::*;
+
+/// A `Storage` that keeps its items as rows of a table in an SQLite database
+///
+/// Each item is a row of table `items`, made of its key and a blob holding the bytes
+/// written by its `Storable` implementation, so the same types can be kept in a
+/// directory or in a single database file. Several changes can be made at once with
+/// `transaction`, or with `Storage::batch`, which runs in a transaction too.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it and its table if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStorage, Error> {
+        SqliteStorage::with_connection(Connection::open(path)?)
+    }
+
+    /// Opens a new database that only lives in memory.
+    pub fn open_in_memory() -> Result<SqliteStorage, Error> {
+        SqliteStorage::with_connection(Connection::open_in_memory()?)
+    }
+
+    /// Uses the database of `connection`, creating the table if needed.
+    pub fn with_connection(connection: Connection) -> Result<SqliteStorage, Error> {
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS items (key TEXT PRIMARY KEY, value BLOB NOT NULL)")?;
+        Ok(SqliteStorage { connection })
+    }
+
+    /// Returns the connection to the database.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Returns where a new snapshot of the database is written: next to its file, or in
+    /// the temporary directory for databases that only live in memory.
+    fn snapshot_path(&self) -> PathBuf {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let stamp = format!("{}.{:09}", time.as_secs(), time.subsec_nanos());
+        match self.connection.path() {
+            Some(path) if !path.is_empty() => PathBuf::from(format!("{}.snapshot-{}", path, stamp)),
+            _ => std::env::temp_dir().join(format!("soter-{}.snapshot-{}", process::id(), stamp)),
+        }
+    }
+
+    /// Runs `f` within a transaction, so that either all or none of the changes it makes
+    /// are kept.
+    ///
+    /// The changes are committed if `f` returns `Ok`, and rolled back otherwise, in
+    /// which case the error of `f` is returned even if rolling back fails too.
+    /// Transactions can be nested, in `f` or in a transaction begun on `connection`:
+    /// each one is a savepoint, whose changes are only committed along with those of
+    /// the outermost transaction, and rolling one back leaves the enclosing ones going.
+    pub fn transaction<R, F>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut SqliteStorage) -> Result<R, Error>,
+    {
+        self.connection.execute_batch("SAVEPOINT soter_transaction")?;
+        let result = f(self).and_then(|result| {
+            self.connection.execute_batch("RELEASE soter_transaction")?;
+            Ok(result)
+        });
+        if result.is_err() {
+            let _ = self
+                .connection
+                .execute_batch("ROLLBACK TO soter_transaction; RELEASE soter_transaction");
+        }
+        result
+    }
+}
+
+impl<T> Storage<T> for SqliteStorage
+where
+    T: StorableBytes,
+{
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error> {
+        let value: Option<Vec<u8>> = self
+            .connection
+            .query_row("SELECT value FROM items WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()?;
+        match value {
+            Some(value) => from_bytes(&value)
+                .map(Some)
+                .map_err(|e| Error::RestoreError(key.to_string(), e.0)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let value = to_bytes(value).map_err(|e| Error::StoreError(key.to_string(), e.0))?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO items (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        let deleted = self.connection.execute("DELETE FROM items WHERE key = ?1", params![key])?;
+        Ok(deleted > 0)
+    }
+
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        let mut statement = self.connection.prepare("SELECT key FROM items")?;
+        let keys = statement.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+        Ok(keys)
+    }
+
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        let found = self
+            .connection
+            .query_row("SELECT 1 FROM items WHERE key = ?1", params![key], |_| Ok(()))
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    fn batch(&mut self, ops: Vec<BatchOp<T>>) -> Result<(), Error> {
+        self.transaction(|storage| {
+            for op in ops {
+                match op {
+                    BatchOp::Put(key, value) => storage.save(&key, &value)?,
+                    BatchOp::Delete(key) => {
+                        Storage::<T>::delete(storage, &key)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { atomic_batch: true }
+    }
+
+    /// Copies the database to a new file with the online backup API of SQLite, which
+    /// gives a consistent copy even while other connections write to the database.
+    ///
+    /// The snapshot of a database file is written next to it, named after the file and
+    /// the current time; the snapshot of a database in memory goes to the temporary
+    /// directory instead.
+    fn snapshot(&self) -> Result<SnapshotHandle, Error> {
+        let path = self.snapshot_path();
+        self.connection.backup(MAIN_DB, &path, None)?;
+        Ok(SnapshotHandle::new(path))
+    }
+
+    /// Replaces the whole database with the copy in `snapshot`, with the online backup
+    /// API of SQLite, which keeps the database locked until all of it is replaced.
+    fn restore_snapshot(&mut self, snapshot: &SnapshotHandle) -> Result<(), Error> {
+        self.connection.restore(MAIN_DB, snapshot.path(), None::<fn(_)>)?;
+        Ok(())
+    }
+}
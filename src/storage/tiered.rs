@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use super::Storage;
+use crate::dir::Error;
+
+/// Which tiers of a `Tiered` storage receive saves
+///
+/// Deletes always go to both tiers, so that a deleted item is not loaded back from the
+/// secondary tier.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TierWrites {
+    /// Saves only go to the primary tier.
+    PrimaryOnly,
+    /// Saves go to the primary tier, then to the secondary tier.
+    Both,
+}
+
+/// A `Storage` made of a fast primary tier in front of a slower secondary tier
+///
+/// Loads are served by the primary tier. When it does not have an item, the item is
+/// loaded from the secondary tier, saved to the primary tier, and returned.
+///
+/// Errors of either tier are returned as they are, and stop the operation where they
+/// happen. The primary tier is written first, so if the secondary tier then fails, the
+/// primary tier already holds the change.
+#[derive(Debug)]
+pub struct Tiered<P, S, T>
+where
+    P: Storage<T>,
+    S: Storage<T>,
+{
+    primary: P,
+    secondary: S,
+    writes: TierWrites,
+    phantom: PhantomData<T>,
+}
+
+impl<P, S, T> Tiered<P, S, T>
+where
+    P: Storage<T>,
+    S: Storage<T>,
+{
+    /// Constructs a new `Tiered` storage from its two tiers.
+    pub fn new(primary: P, secondary: S, writes: TierWrites) -> Tiered<P, S, T> {
+        Tiered {
+            primary,
+            secondary,
+            writes,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the primary tier.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns the secondary tier.
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+}
+
+impl<P, S, T> Storage<T> for Tiered<P, S, T>
+where
+    P: Storage<T>,
+    S: Storage<T>,
+{
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error> {
+        if let Some(v) = self.primary.load(key)? {
+            return Ok(Some(v));
+        }
+        match self.secondary.load(key)? {
+            Some(v) => {
+                self.primary.save(key, &v)?;
+                Ok(Some(v))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.primary.save(key, value)?;
+        if self.writes == TierWrites::Both {
+            self.secondary.save(key, value)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        let existed = self.primary.delete(key)?;
+        Ok(self.secondary.delete(key)? || existed)
+    }
+
+    /// Returns the keys of both tiers.
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        let mut keys: HashSet<String> = self.primary.keys()?.into_iter().collect();
+        keys.extend(self.secondary.keys()?);
+        Ok(keys.into_iter().collect())
+    }
+
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        Ok(self.primary.contains(key)? || self.secondary.contains(key)?)
+    }
+}
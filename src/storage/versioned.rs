@@ -0,0 +1,166 @@
+use std::marker::PhantomData;
+
+use super::{SnapshotHandle, Storage};
+use crate::dir::Error;
+
+/// Returns the key version `n` of the item of key `key` is kept under.
+fn version_key(key: &str, n: usize) -> String {
+    format!(".{}.v{}", key, n)
+}
+
+/// Returns whether `key` is the key of a previous version kept by a `Versioned`.
+fn is_version_key(key: &str) -> bool {
+    match key.rfind(".v") {
+        Some(i) if key.starts_with('.') && i > 0 => {
+            let n = &key[i + 2..];
+            !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+        }
+        _ => false,
+    }
+}
+
+/// A decorator that keeps the previous versions of the items of a `Storage`
+///
+/// Every time an item is replaced or deleted, the item it had until then is kept as
+/// its most recent previous version, up to `retention` versions per key, the oldest
+/// being dropped beyond that. Version `1` of a key is always its most recent previous
+/// version, `2` the one before, and so on: versions are kept in the backend under the
+/// hidden keys `.<key>.v1`, `.<key>.v2`, ... which `DirStorage` and `DirBackend` skip
+/// like every hidden file, and which `keys` leaves out for other backends.
+///
+/// Keeping a new version renumbers all the older ones, which loads and saves each of
+/// them again, so writes cost as many backend operations as there are versions kept.
+/// Errors of the backend are returned as they are, and can leave the versions of a key
+/// only partly renumbered.
+#[derive(Debug)]
+pub struct Versioned<S, T>
+where
+    S: Storage<T>,
+{
+    backend: S,
+    retention: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<S, T> Versioned<S, T>
+where
+    S: Storage<T>,
+{
+    /// Constructs a new `Versioned` storage on top of `backend`, keeping up to
+    /// `retention` previous versions of each item.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `retention` is 0, which would keep no versions at all.
+    pub fn new(backend: S, retention: usize) -> Versioned<S, T> {
+        assert!(retention > 0, "a Versioned storage keeps at least one version");
+        Versioned {
+            backend,
+            retention,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the backend the items and their versions are kept in.
+    pub fn backend(&self) -> &S {
+        &self.backend
+    }
+
+    /// Returns the previous versions kept for `key`, from the most recent one, `1`, to
+    /// the oldest.
+    ///
+    /// The versions are found with `Storage::contains`, so backends that can tell an
+    /// item exists without decoding it, like `DirBackend`, decode none of them.
+    pub fn history(&mut self, key: &str) -> Result<Vec<usize>, Error> {
+        let mut versions = Vec::new();
+        for n in 1..=self.retention {
+            if !self.backend.contains(&version_key(key, n))? {
+                break;
+            }
+            versions.push(n);
+        }
+        Ok(versions)
+    }
+
+    /// Returns version `n` of the item of key `key`, if it is kept; `1` is the most
+    /// recent previous version.
+    ///
+    /// The version is not made current again: saving it does that, which keeps the
+    /// current item as the most recent version in turn.
+    pub fn restore_version(&mut self, key: &str, n: usize) -> Result<Option<T>, Error> {
+        if n == 0 || n > self.retention {
+            return Ok(None);
+        }
+        self.backend.load(&version_key(key, n))
+    }
+
+    /// Keeps the current item of `key`, if any, as its most recent previous version,
+    /// renumbering the older ones and dropping those beyond the retention.
+    ///
+    /// Returns whether there was a current item.
+    fn push_version(&mut self, key: &str) -> Result<bool, Error> {
+        let current = match self.backend.load(key)? {
+            Some(current) => current,
+            None => return Ok(false),
+        };
+
+        // Each version overwrites the next one, so the oldest is dropped when all the
+        // versions allowed are kept already.
+        let kept = self.history(key)?.len();
+        for n in (1..=kept.min(self.retention - 1)).rev() {
+            if let Some(version) = self.backend.load(&version_key(key, n))? {
+                self.backend.save(&version_key(key, n + 1), &version)?;
+            }
+        }
+        self.backend.save(&version_key(key, 1), &current)?;
+
+        // Versions beyond the retention are left by storages kept with more of them.
+        let mut n = self.retention + 1;
+        while self.backend.delete(&version_key(key, n))? {
+            n += 1;
+        }
+        Ok(true)
+    }
+}
+
+impl<S, T> Storage<T> for Versioned<S, T>
+where
+    S: Storage<T>,
+{
+    fn load(&mut self, key: &str) -> Result<Option<T>, Error> {
+        self.backend.load(key)
+    }
+
+    fn save(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.push_version(key)?;
+        self.backend.save(key, value)
+    }
+
+    /// Deletes the item of `key`, keeping it as its most recent previous version.
+    fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        if !self.push_version(key)? {
+            return Ok(false);
+        }
+        self.backend.delete(key)
+    }
+
+    /// Returns the keys of the current items, leaving out their previous versions.
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        let mut keys = self.backend.keys()?;
+        keys.retain(|key| !is_version_key(key));
+        Ok(keys)
+    }
+
+    fn contains(&mut self, key: &str) -> Result<bool, Error> {
+        self.backend.contains(key)
+    }
+
+    /// Snapshots the backend, previous versions included.
+    fn snapshot(&self) -> Result<SnapshotHandle, Error> {
+        self.backend.snapshot()
+    }
+
+    fn restore_snapshot(&mut self, snapshot: &SnapshotHandle) -> Result<(), Error> {
+        self.backend.restore_snapshot(snapshot)
+    }
+}
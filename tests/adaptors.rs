@@ -0,0 +1,234 @@
+use tempdir::TempDir;
+
+use std::io::{Read, Write};
+
+use soter::adaptors::*;
+use soter::dir::DirStorage;
+use soter::storable::*;
+
+/// A temperature, stored in tenths of a degree.
+#[derive(Debug, PartialEq)]
+struct Celsius(i64);
+
+/// The legacy format of `Celsius`, stored in whole degrees with a unit.
+struct LegacyCelsius(i64);
+
+impl From<LegacyCelsius> for Celsius {
+    fn from(legacy: LegacyCelsius) -> Celsius {
+        Celsius(legacy.0 * 10)
+    }
+}
+
+impl<W: Write, R: Read> Storable<W, R> for Celsius {
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        Storable::<W, R>::restore(reader).map(Celsius)
+    }
+
+    fn store(&self, writer: W) -> Result<(), StorableStoreError> {
+        Storable::<W, R>::store(&self.0, writer)
+    }
+}
+
+impl<W: Write, R: Read> Storable<W, R> for LegacyCelsius {
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        let text: String = Storable::<W, R>::restore(reader)?;
+        let degrees = text.strip_suffix("C").ok_or_else(|| StorableRestoreError("no unit".into()))?;
+        degrees.parse().map(LegacyCelsius).map_err(|_| StorableRestoreError("bad degrees".into()))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        write!(writer, "{}C", self.0).map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
+
+#[test]
+fn fallback() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("new"), "215").unwrap();
+    std::fs::write(dir.path().join("old"), "21C").unwrap();
+
+    let dir_storage: DirStorage<Fallback<Celsius, LegacyCelsius>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(dir_storage.get("new").unwrap().0, Celsius(215));
+    assert_eq!(dir_storage.get("old").unwrap().0, Celsius(210));
+
+    dir_storage.store(dir_str).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("old")).unwrap(), "210");
+
+    std::fs::write(dir.path().join("bad"), "hot").unwrap();
+    assert!(DirStorage::<Fallback<Celsius, LegacyCelsius>>::restore(dir_str).is_err());
+}
+
+/// A value that only ever reads its first byte.
+#[derive(Debug, PartialEq)]
+struct FirstByte(u8);
+
+impl<W: Write, R: Read> Storable<W, R> for FirstByte {
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut byte = [0];
+        reader
+            .read_exact(&mut byte)
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        Ok(FirstByte(byte[0]))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        writer
+            .write_all(&[self.0])
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
+
+#[test]
+fn length_prefixed() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<LengthPrefixed<FirstByte>> = DirStorage::default();
+    dir_storage.insert("a", LengthPrefixed(FirstByte(7)));
+    dir_storage.store(dir_str).unwrap();
+    assert_eq!(std::fs::read(dir.path().join("a")).unwrap(), [1, 0, 0, 0, 0, 0, 0, 0, 7]);
+    let restored: DirStorage<LengthPrefixed<FirstByte>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    let mut unread = 2u64.to_le_bytes().to_vec();
+    unread.extend_from_slice(&[7, 8]);
+    std::fs::write(dir.path().join("a"), &unread).unwrap();
+    assert!(DirStorage::<LengthPrefixed<FirstByte>>::restore(dir_str).is_err());
+
+    let mut truncated = 2u64.to_le_bytes().to_vec();
+    truncated.push(7);
+    std::fs::write(dir.path().join("a"), &truncated).unwrap();
+    assert!(DirStorage::<LengthPrefixed<FirstByte>>::restore(dir_str).is_err());
+}
+
+trait Shape: Tagged {
+    fn area(&self) -> u32;
+}
+
+struct Square(u32);
+
+struct Rectangle(u32, u32);
+
+impl Tagged for Square {
+    fn type_tag(&self) -> &str {
+        "square"
+    }
+
+    fn value_bytes(&self) -> Result<Vec<u8>, StorableStoreError> {
+        to_bytes(&self.0)
+    }
+}
+
+impl Tagged for Rectangle {
+    fn type_tag(&self) -> &str {
+        "rectangle"
+    }
+
+    fn value_bytes(&self) -> Result<Vec<u8>, StorableStoreError> {
+        to_bytes(&format!("{}x{}", self.0, self.1))
+    }
+}
+
+impl Shape for Square {
+    fn area(&self) -> u32 {
+        self.0 * self.0
+    }
+}
+
+impl Shape for Rectangle {
+    fn area(&self) -> u32 {
+        self.0 * self.1
+    }
+}
+
+fn shapes() -> Registry<dyn Shape> {
+    Registry::new()
+        .register("square", |bytes| {
+            from_bytes::<u32>(bytes).map(|side| Box::new(Square(side)) as Box<dyn Shape>)
+        })
+        .register("rectangle", |bytes| {
+            let sides: String = from_bytes(bytes)?;
+            let (width, height) = sides
+                .split_once('x')
+                .ok_or_else(|| StorableRestoreError("not a rectangle".to_string()))?;
+            let parse = |side: &str| side.parse().map_err(|_| StorableRestoreError("not a side".to_string()));
+            Ok(Box::new(Rectangle(parse(width)?, parse(height)?)) as Box<dyn Shape>)
+        })
+}
+
+#[test]
+fn typed_box() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let registry = shapes();
+
+    let mut dir_storage: DirStorage<TypedBox<dyn Shape>> = DirStorage::default();
+    dir_storage.insert("a", TypedBox(Box::new(Square(3))));
+    dir_storage.insert("b", TypedBox(Box::new(Rectangle(2, 5))));
+    dir_storage.store_with_ctx(dir_str, &registry).unwrap();
+    assert_eq!(std::fs::read(dir.path().join("a")).unwrap(), b"square\x003");
+
+    let restored: DirStorage<TypedBox<dyn Shape>> = DirStorage::restore_with_ctx(dir_str, &registry).unwrap();
+    assert_eq!(restored.get("a").unwrap().area(), 9);
+    assert_eq!(restored.get("b").unwrap().type_tag(), "rectangle");
+    assert_eq!(restored.get("b").unwrap().area(), 10);
+
+    std::fs::write(dir.path().join("c"), b"circle\x002").unwrap();
+    let message = match DirStorage::<TypedBox<dyn Shape>>::restore_with_ctx(dir_str, &registry) {
+        Err(e) => e.to_string(),
+        Ok(_) => panic!("restored an unknown type tag"),
+    };
+    assert!(message.ends_with("unknown type tag \"circle\""), "{}", message);
+
+    let unregistered: Registry<dyn Shape> = Registry::new();
+    assert!(dir_storage.store_with_ctx(dir_str, &unregistered).is_err());
+}
+
+#[test]
+fn chunked() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let value: String = (0..200_000).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+
+    let mut dir_storage: DirStorage<Chunked<String>> = DirStorage::default();
+    dir_storage.insert("a", Chunked(value));
+    dir_storage.store(dir_str).unwrap();
+    let restored: DirStorage<Chunked<String>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    // 4 chunks of 64 KiB make a table of 12 + 4 * 4 bytes.
+    let mut bytes = std::fs::read(dir.path().join("a")).unwrap();
+    bytes[28 + 2 * 64 * 1024 + 10] ^= 1;
+    std::fs::write(dir.path().join("a"), &bytes).unwrap();
+    let message = DirStorage::<Chunked<String>>::restore(dir_str).unwrap_err().to_string();
+    assert!(message.ends_with("chunk 2 is corrupt"), "{}", message);
+
+    bytes.truncate(bytes.len() - 1);
+    std::fs::write(dir.path().join("a"), &bytes).unwrap();
+    assert!(DirStorage::<Chunked<String>>::restore(dir_str).is_err());
+}
+
+#[test]
+fn with_trailer() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<WithTrailer<Chunked<String>, String>> = DirStorage::default();
+    dir_storage.insert("a", WithTrailer::new(Chunked("payload".to_string()), "sig".to_string()));
+    dir_storage.store(dir_str).unwrap();
+    let bytes = std::fs::read(dir.path().join("a")).unwrap();
+    assert!(bytes.ends_with(b"sig\x03\0\0\0\0\0\0\0"));
+
+    let restored: DirStorage<WithTrailer<Chunked<String>, String>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    let mut damaged = bytes.clone();
+    let len = damaged.len();
+    damaged[len - 8] = 0xff;
+    std::fs::write(dir.path().join("a"), &damaged).unwrap();
+    assert!(DirStorage::<WithTrailer<Chunked<String>, String>>::restore(dir_str).is_err());
+
+    std::fs::write(dir.path().join("a"), &bytes[..4]).unwrap();
+    assert!(DirStorage::<WithTrailer<Chunked<String>, String>>::restore(dir_str).is_err());
+}
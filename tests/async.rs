@@ -0,0 +1,156 @@
+#![cfg(feature = "async")]
+
+use tempdir::TempDir;
+
+use soter::dir::{DirStorage, StoreEvent};
+use soter::storable::*;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[derive(Debug, PartialEq)]
+struct Line(String);
+
+impl<W, R> AsyncStorable<W, R> for Line
+where
+    W: AsyncWrite + Unpin + Send,
+    R: AsyncRead + Unpin + Send,
+{
+    async fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut s = String::new();
+        reader
+            .read_to_string(&mut s)
+            .await
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        s.pop();
+        Ok(Line(s))
+    }
+
+    async fn store(&self, writer: &mut W) -> Result<(), StorableStoreError> {
+        writer
+            .write_all(format!("{}\n", self.0).as_bytes())
+            .await
+            .map_err(|e| StorableStoreError(e.to_string()))
+    }
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+#[test]
+fn async_storable() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Line> = DirStorage::default();
+    dir_storage.insert("a", Line("first".to_string()));
+    let (sender, receiver) = std::sync::mpsc::channel();
+    dir_storage.subscribe(move |event| {
+        let _ = sender.send(event.clone());
+    });
+    block_on(dir_storage.store_async(dir_str)).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("a")).unwrap(), "first\n");
+    let events: Vec<StoreEvent> = receiver.try_iter().collect();
+    assert_eq!(events, vec![StoreEvent::Stored { key: "a".to_string() }]);
+
+    let restored: DirStorage<Line> = block_on(DirStorage::restore_async(dir_str)).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(restored.stats().files_read, 1);
+}
+
+#[test]
+fn store_async_atomically() {
+    use soter::dir::Options;
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "old\n").unwrap();
+    std::fs::hard_link(dir.path().join("a"), dir.path().join("link")).unwrap();
+
+    let options = Options::default().atomic_writes(true);
+    let mut dir_storage: DirStorage<Line> = DirStorage::with_options(HashMap::new(), options);
+    dir_storage.insert("a", Line("new".to_string()));
+    block_on(dir_storage.store_async(dir_str)).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("a")).unwrap(), "new\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("link")).unwrap(), "old\n");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn store_async_backup() {
+    use soter::dir::Options;
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "old\n").unwrap();
+
+    let options = Options::default().backup_on_overwrite(1);
+    let mut dir_storage: DirStorage<Line> = DirStorage::with_options(HashMap::new(), options);
+    dir_storage.insert("a", Line("new".to_string()));
+    block_on(dir_storage.store_async(dir_str)).unwrap();
+
+    let backups: Vec<_> = std::fs::read_dir(dir.path().join(".bak")).unwrap().map(Result::unwrap).collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(std::fs::read_to_string(backups[0].path()).unwrap(), "old\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn store_async_preserve_permissions() {
+    use soter::dir::Options;
+    use std::collections::HashMap;
+    use std::fs::{metadata, set_permissions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let path = dir.path().join("a");
+    std::fs::write(&path, "old\n").unwrap();
+    set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+
+    for options in [Options::default(), Options::default().atomic_writes(true)] {
+        let options = options.preserve_permissions(true);
+        let mut dir_storage: DirStorage<Line> = DirStorage::with_options(HashMap::new(), options);
+        dir_storage.insert("a", Line("new".to_string()));
+        block_on(dir_storage.store_async(dir_str)).unwrap();
+        assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+}
+
+#[test]
+fn async_framing() {
+    use soter::dir::{Framing, Options};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().framing(Framing::new(&b"MAGIC\n"[..], &b"END"[..]));
+
+    let mut dir_storage: DirStorage<Line> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("a", Line("first".to_string()));
+    block_on(dir_storage.store_async(dir_str)).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("a")).unwrap(), "MAGIC\nfirst\nEND");
+
+    let restored: DirStorage<Line> =
+        block_on(DirStorage::restore_async_with_options(dir_str, options.clone())).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(restored.options(), &options);
+    let restored: DirStorage<String> = DirStorage::restore_with_options(dir_str, options).unwrap();
+    assert_eq!(restored.get("a").unwrap(), "first\n");
+}
+
+#[test]
+fn blocking_bridge() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Blocking<u32>> = DirStorage::default();
+    dir_storage.insert("a", Blocking(42));
+    block_on(dir_storage.store_async(dir_str)).unwrap();
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(*restored.get("a").unwrap(), 42);
+    let restored: DirStorage<Blocking<u32>> = block_on(DirStorage::restore_async(dir_str)).unwrap();
+    assert_eq!(restored, dir_storage);
+}
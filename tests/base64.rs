@@ -0,0 +1,25 @@
+#![cfg(feature = "base64")]
+
+use tempdir::TempDir;
+
+use soter::adaptors::*;
+use soter::dir::DirStorage;
+
+#[test]
+fn base64() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Base64<String>> = DirStorage::default();
+    dir_storage.insert("a", Base64("\u{0}binary\u{1}".to_string()));
+    dir_storage.store(dir_str).unwrap();
+
+    let contents = std::fs::read_to_string(dir.path().join("a")).unwrap();
+    assert_eq!(contents, "AGJpbmFyeQE=");
+
+    let restored: DirStorage<Base64<String>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    std::fs::write(dir.path().join("a"), "not base64!").unwrap();
+    assert!(DirStorage::<Base64<String>>::restore(dir_str).is_err());
+}
@@ -1,6 +1,11 @@
 use tempdir::TempDir;
 
-use soter::dir::DirStorage;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime};
+
+use soter::dir::{DirStorage, Error};
+use soter::storable::*;
 
 #[test]
 fn test() {
@@ -20,3 +25,1637 @@ fn test() {
     assert_eq!(*new_dir_storage.get("2").unwrap(), 2);
     assert_eq!(*new_dir_storage.get("3").unwrap(), 3);
 }
+
+#[test]
+fn insert_next() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("7", 7);
+    dir_storage.store(dir_str).unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    assert_eq!(dir_storage.insert_next(dir_str, 8).unwrap(), "8");
+    assert_eq!(dir_storage.insert_next(dir_str, 9).unwrap(), "9");
+
+    // The counter survives even if the new items were never stored.
+    let mut other: DirStorage<u32> = DirStorage::default();
+    assert_eq!(other.insert_next(dir_str, 10).unwrap(), "10");
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert!(restored.contains_key("7"));
+    assert!(!restored.contains_key(".soter_counter"));
+}
+
+#[test]
+fn insert_next_sharded() {
+    use soter::dir::{Options, ShardScheme};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().shards(ShardScheme::Prefix(1));
+
+    // The shard "1" holds the item "12", so the next key follows "12", not "1".
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("12", 12);
+    dir_storage.store(dir_str).unwrap();
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options);
+    assert_eq!(dir_storage.insert_next(dir_str, 13).unwrap(), "13");
+    // The counter is written through a temporary file, which does not stay behind.
+    let names: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert!(names.iter().all(|name| !name.ends_with(".tmp")), "{:?}", names);
+}
+
+struct Scaled(u32);
+
+impl<W: Write, R: Read> StorableWithCtx<u32, W, R> for Scaled {
+    fn restore(reader: R, factor: &u32) -> Result<Self, StorableRestoreError> {
+        let stored: u32 = Storable::<W, R>::restore(reader)?;
+        Ok(Scaled(stored * factor))
+    }
+
+    fn store(&self, writer: W, factor: &u32) -> Result<(), StorableStoreError> {
+        Storable::<W, R>::store(&(self.0 / factor), writer)
+    }
+}
+
+#[test]
+fn restore_with_ctx() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Scaled> = DirStorage::default();
+    dir_storage.insert("a", Scaled(30));
+    dir_storage.store_with_ctx(dir_str, &10).unwrap();
+
+    let new_dir_storage: DirStorage<Scaled> = DirStorage::restore_with_ctx(dir_str, &100).unwrap();
+    assert_eq!(new_dir_storage.get("a").unwrap().0, 300);
+}
+
+#[test]
+fn restore_with_ctx_and_options() {
+    use soter::dir::{Framing, KeyEncoding, NameHasher, Options};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().framing(Framing::new(&b"MAGIC\n"[..], &b"\nEND"[..]));
+
+    let mut dir_storage: DirStorage<Scaled> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("a", Scaled(30));
+    dir_storage.store_with_ctx(dir_str, &10).unwrap();
+    assert_eq!(std::fs::read(dir.path().join("a")).unwrap(), b"MAGIC\n3\nEND");
+
+    let restored: DirStorage<Scaled> = DirStorage::restore_with_ctx_and_options(dir_str, &10, options.clone()).unwrap();
+    assert_eq!(restored.get("a").unwrap().0, 30);
+    assert_eq!(restored.options(), &options);
+    assert!(DirStorage::<Scaled>::restore_with_ctx(dir_str, &10).is_err());
+
+    // Keys come back decoded, not as the names of their files.
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().key_encoding(KeyEncoding::Windows);
+    let mut dir_storage: DirStorage<Scaled> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("a/b", Scaled(30));
+    dir_storage.store_with_ctx(dir_str, &10).unwrap();
+    assert!(dir.path().join("a%2Fb").is_file());
+
+    let restored: DirStorage<Scaled> = DirStorage::restore_with_ctx_and_options(dir_str, &10, options).unwrap();
+    assert_eq!(restored.get("a/b").unwrap().0, 30);
+
+    // Hashed file names are mapped back to their keys through the manifest.
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().name_hasher(NameHasher::default());
+    let mut dir_storage: DirStorage<Scaled> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("secret", Scaled(30));
+    dir_storage.store_with_ctx(dir_str, &10).unwrap();
+    assert!(dir.path().join(NameHasher::default().hash("secret")).is_file());
+
+    let restored: DirStorage<Scaled> = DirStorage::restore_with_ctx_and_options(dir_str, &10, options).unwrap();
+    assert_eq!(restored.get("secret").unwrap().0, 30);
+    assert_eq!(restored.loaded_len(), 1);
+}
+
+#[test]
+fn compact_keys() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    for k in &[1, 2, 5, 9] {
+        dir_storage.insert(k.to_string(), *k);
+    }
+    dir_storage.store(dir_str).unwrap();
+
+    // "2" takes the place of "1", whose file has not been moved yet.
+    dir_storage
+        .compact_keys(dir_str, |k| match k {
+            "1" => "2".to_string(),
+            "2" => "1".to_string(),
+            "5" => "3".to_string(),
+            _ => "4".to_string(),
+        })
+        .unwrap();
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(*restored.get("1").unwrap(), 2);
+    assert_eq!(*restored.get("2").unwrap(), 1);
+    assert_eq!(*restored.get("3").unwrap(), 5);
+    assert_eq!(*restored.get("4").unwrap(), 9);
+
+    assert!(dir_storage.compact_keys(dir_str, |_| "0".to_string()).is_err());
+}
+
+#[test]
+fn compact_keys_failures() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    for k in &[1, 2, 5] {
+        dir_storage.insert(k.to_string(), *k);
+    }
+    dir_storage.store(dir_str).unwrap();
+    let unchanged = |dir_storage: &DirStorage<u32>| {
+        let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+        assert_eq!(&restored, dir_storage);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+    };
+
+    // "3" has a file, but no item in memory.
+    std::fs::write(dir.path().join("3"), "3").unwrap();
+    match dir_storage.compact_keys(dir_str, |k| if k == "5" { "3".to_string() } else { k.to_string() }) {
+        Err(Error::KeyCollision(key)) => assert_eq!(key, "3"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(std::fs::read_to_string(dir.path().join("3")).unwrap(), "3");
+    std::fs::remove_file(dir.path().join("3")).unwrap();
+
+    // The hidden name of "5" is taken, so the first phase fails and is rolled back.
+    std::fs::create_dir_all(dir.path().join(".5.compact").join("taken")).unwrap();
+    let remap = |k: &str| (k.parse::<u32>().unwrap() + 1).to_string();
+    assert!(dir_storage.compact_keys(dir_str, remap).is_err());
+    std::fs::remove_dir_all(dir.path().join(".5.compact")).unwrap();
+    assert!(dir_storage.contains_key("5"));
+    unchanged(&dir_storage);
+
+    dir_storage.compact_keys(dir_str, remap).unwrap();
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(*restored.get("6").unwrap(), 5);
+}
+
+#[test]
+fn recover_compact_keys() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("1", 1);
+    dir_storage.insert("2", 2);
+    dir_storage.store(dir_str).unwrap();
+
+    // Stopped while moving "1" and "2" to hidden names, on the way to "2" and "3".
+    std::fs::write(dir.path().join(".compacting"), "1\x002\x002\x003\x00").unwrap();
+    std::fs::rename(dir.path().join("1"), dir.path().join(".1.compact")).unwrap();
+    assert_eq!(dir_storage.recover(dir_str).unwrap(), vec![".1.compact".to_string()]);
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert!(!dir.path().join(".compacting").exists());
+
+    // Stopped while moving them to their new names.
+    std::fs::write(dir.path().join(".compacted"), "1\x002\x002\x003\x00").unwrap();
+    std::fs::rename(dir.path().join("2"), dir.path().join("3")).unwrap();
+    std::fs::rename(dir.path().join("1"), dir.path().join(".1.compact")).unwrap();
+    assert_eq!(dir_storage.recover(dir_str).unwrap(), vec![".1.compact".to_string()]);
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(*restored.get("2").unwrap(), 1);
+    assert_eq!(*restored.get("3").unwrap(), 2);
+    assert!(!restored.contains_key("1"));
+    assert!(!dir.path().join(".compacted").exists());
+}
+
+#[test]
+fn compact_keys_with_name_hasher() {
+    use soter::dir::{NameHasher, Options};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let hasher = NameHasher::default();
+    let options = Options::default().name_hasher(hasher);
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    for k in &[1, 2, 5] {
+        dir_storage.insert(k.to_string(), *k);
+    }
+    dir_storage.store(dir_str).unwrap();
+    dir_storage
+        .compact_keys(dir_str, |k| match k {
+            "1" => "2".to_string(),
+            "2" => "1".to_string(),
+            _ => "3".to_string(),
+        })
+        .unwrap();
+    assert!(!dir.path().join(hasher.hash("5")).exists());
+    assert_eq!(std::fs::read_to_string(dir.path().join(hasher.hash("3"))).unwrap(), "5");
+
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(dir_str, options).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(*restored.get("1").unwrap(), 2);
+    assert_eq!(*restored.get("3").unwrap(), 5);
+    assert!(restored.get("5").is_none());
+}
+
+/// Stores only the integer part, so it never reads back equal to a fraction.
+#[derive(Debug, PartialEq)]
+struct Lossy(f64);
+
+impl<W: Write, R: Read> Storable<W, R> for Lossy {
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        let stored: i64 = Storable::<W, R>::restore(reader)?;
+        Ok(Lossy(stored as f64))
+    }
+
+    fn store(&self, writer: W) -> Result<(), StorableStoreError> {
+        Storable::<W, R>::store(&(self.0 as i64), writer)
+    }
+}
+
+#[test]
+fn verify_after_write() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Lossy> = DirStorage::default();
+    dir_storage.insert("whole", Lossy(2.0));
+    dir_storage.insert("fraction", Lossy(2.5));
+    dir_storage.store(dir_str).unwrap();
+
+    dir_storage.set_verify_after_write(true);
+    dir_storage.store_single(dir_str, "whole").unwrap();
+    match dir_storage.store_single(dir_str, "fraction") {
+        Err(Error::VerificationFailed(key)) => assert_eq!(key, "fraction"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn export_and_load_stream() {
+    let mut dir_storage: DirStorage<String> = DirStorage::default();
+    dir_storage.insert("a", "first".to_string());
+    dir_storage.insert("b", "second".to_string());
+
+    let mut stream = Vec::new();
+    dir_storage.export(&mut stream).unwrap();
+    let loaded: DirStorage<String> = DirStorage::load_stream(&stream[..]).unwrap();
+    assert_eq!(loaded, dir_storage);
+
+    stream.truncate(stream.len() - 1);
+    match DirStorage::<String>::load_stream(&stream[..]) {
+        Err(Error::RestoreError(record, _)) => assert_eq!(record, "record 1"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn touch() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("1", 1);
+    dir_storage.store(dir_str).unwrap();
+
+    let path = dir.path().join("1");
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    File::options().write(true).open(&path).unwrap().set_modified(old).unwrap();
+
+    dir_storage.touch(dir_str, "1").unwrap();
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    assert!(modified > old + Duration::from_secs(60));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "1");
+
+    assert!(matches!(dir_storage.touch(dir_str, "2"), Err(Error::NotFound(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn special_files() {
+    use soter::dir::{Options, SpecialFiles};
+    use std::os::unix::net::UnixListener;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("1", 1);
+    dir_storage.store(dir_str).unwrap();
+    let _socket = UnixListener::bind(dir.path().join("socket")).unwrap();
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    let options = Options::default().special_files(SpecialFiles::Error);
+    let result = DirStorage::<u32>::restore_with_options(dir_str, options);
+    assert!(matches!(result, Err(Error::SpecialFile(_))));
+}
+
+#[test]
+fn delete_matching() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("tmp-1", 1);
+    dir_storage.insert("tmp-22", 22);
+    dir_storage.insert("keep", 3);
+    dir_storage.store(dir_str).unwrap();
+
+    let mut matching = dir_storage.match_matching(dir_str, "tmp-?").unwrap();
+    assert_eq!(matching, vec!["tmp-1".to_string()]);
+
+    matching = dir_storage.delete_matching(dir_str, "tmp-*").unwrap();
+    matching.sort();
+    assert_eq!(matching, vec!["tmp-1".to_string(), "tmp-22".to_string()]);
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert!(restored.contains_key("keep"));
+    assert!(!restored.contains_key("tmp-1"));
+    assert!(!restored.contains_key("tmp-22"));
+}
+
+#[test]
+fn stats() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("1", 1);
+    dir_storage.insert("2", 22);
+    dir_storage.store(dir_str).unwrap();
+    assert!(dir_storage.store_single(dir_str, "3").is_err());
+
+    let stats = dir_storage.stats();
+    assert_eq!(stats.stores, 1);
+    assert_eq!(stats.files_written, 2);
+    assert_eq!(stats.bytes_written, 3);
+    assert_eq!(stats.errors, 1);
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    let stats = restored.stats();
+    assert_eq!(stats.restores, 1);
+    assert_eq!(stats.files_read, 2);
+    assert_eq!(stats.bytes_read, 3);
+
+    restored.reset_stats();
+    assert_eq!(restored.stats(), Default::default());
+}
+
+#[test]
+fn stats_shared_between_threads() {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<DirStorage<String>>();
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("1", 1);
+    dir_storage.subscribe(|_| {});
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| dir_storage.store_single(dir_str, "1").unwrap());
+        }
+    });
+    assert_eq!(dir_storage.stats().files_written, 4);
+}
+
+#[test]
+fn rename_file_raw() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("old", 1);
+    dir_storage.store(dir_str).unwrap();
+
+    dir_storage.rename_file_raw(dir_str, "old", "new").unwrap();
+    assert!(dir_storage.contains_key("old"));
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(*restored.get("new").unwrap(), 1);
+    assert!(!restored.contains_key("old"));
+
+    let result = dir_storage.rename_file_raw(dir_str, "old", "new");
+    assert!(matches!(result, Err(Error::NotFound(ref name)) if name == "old"));
+}
+
+#[test]
+fn store_over_directory() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub").join("inner"), "kept").unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("sub", 1);
+    let result = dir_storage.store(dir_str);
+    assert!(matches!(result, Err(Error::PathIsDirectory(ref key)) if key == "sub"));
+    assert!(dir.path().join("sub").join("inner").is_file());
+}
+
+#[test]
+fn store_over_directory_reports_key() {
+    use soter::dir::{NameHasher, Options};
+    use std::collections::HashMap;
+
+    // The file of "sub" is named after its hash, but the error names the key.
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let hasher = NameHasher::default();
+    std::fs::create_dir(dir.path().join(hasher.hash("sub"))).unwrap();
+
+    let options = Options::default().name_hasher(hasher);
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options);
+    dir_storage.insert("sub", 1);
+    match dir_storage.store_single(dir_str, "sub") {
+        Err(Error::PathIsDirectory(key)) => assert_eq!(key, "sub"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn any_and_sample() {
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    assert!(dir_storage.any().is_none());
+    assert!(dir_storage.sample(2).is_empty());
+
+    dir_storage.insert("1", 1);
+    dir_storage.insert("2", 2);
+    dir_storage.insert("3", 3);
+    let (k, v) = dir_storage.any().unwrap();
+    assert_eq!(*k, v.to_string());
+    assert_eq!(dir_storage.sample(2).len(), 2);
+    assert_eq!(dir_storage.sample(5).len(), 3);
+}
+
+#[test]
+fn atomic_writes() {
+    use soter::dir::Options;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join(".1.123-4-5.tmp"), "orphan").unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.set_options(Options::default().atomic_writes(true));
+    dir_storage.insert("1", 1);
+    dir_storage.store(dir_str).unwrap();
+    dir_storage.insert("1", 11);
+    dir_storage.store(dir_str).unwrap();
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    assert_eq!(dir_storage.recover(dir_str).unwrap(), vec![".1.123-4-5.tmp".to_string()]);
+    let names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names, vec!["1"]);
+}
+
+#[test]
+fn rc_values() {
+    use std::rc::Rc;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let shared = Rc::new("shared".to_string());
+    let mut dir_storage: DirStorage<Rc<String>> = DirStorage::default();
+    dir_storage.insert("a", shared.clone());
+    dir_storage.insert("b", shared);
+    dir_storage.store(dir_str).unwrap();
+
+    let restored: DirStorage<Rc<String>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert!(!Rc::ptr_eq(restored.get("a").unwrap(), restored.get("b").unwrap()));
+}
+
+#[test]
+fn fetch_add() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut a: DirStorage<i64> = DirStorage::default();
+    let mut b: DirStorage<i64> = DirStorage::default();
+    assert_eq!(a.fetch_add(dir_str, "hits", 5).unwrap(), 5);
+    assert_eq!(b.fetch_add(dir_str, "hits", -2).unwrap(), 3);
+    assert_eq!(a.fetch_add(dir_str, "hits", 1).unwrap(), 4);
+    assert_eq!(*a.get("hits").unwrap(), 4);
+
+    let mut restored: DirStorage<i64> = DirStorage::default();
+    assert!(restored.restore_single(dir_str, "hits").unwrap());
+    assert_eq!(*restored.get("hits").unwrap(), 4);
+    assert!(!restored.restore_single(dir_str, "misses").unwrap());
+}
+
+#[test]
+fn framed() {
+    use soter::dir::Framed;
+    use std::fs::OpenOptions;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let framed: Framed<String> = Framed::new(dir.path().join("log"));
+    assert_eq!(framed.iter().unwrap().count(), 0);
+
+    framed.append(&"first".to_string()).unwrap();
+    framed.append(&"second".to_string()).unwrap();
+
+    let mut file = OpenOptions::new().append(true).open(framed.path()).unwrap();
+    file.write_all(&10u64.to_le_bytes()).unwrap();
+    file.write_all(b"thi").unwrap();
+
+    let mut frames = framed.iter().unwrap();
+    let values: Vec<String> = frames.by_ref().map(Result::unwrap).collect();
+    assert_eq!(values, vec!["first", "second"]);
+    assert!(frames.truncated());
+
+    assert!(framed.repair().unwrap());
+    assert!(!framed.repair().unwrap());
+    framed.append(&"third".to_string()).unwrap();
+    let values: Vec<String> = framed.iter().unwrap().map(Result::unwrap).collect();
+    assert_eq!(values, vec!["first", "second", "third"]);
+}
+
+#[test]
+fn restore_from_bytes() {
+    let entries = vec![
+        (".hidden".to_string(), b"1".to_vec()),
+        ("two".to_string(), b"2\n".to_vec()),
+    ];
+    let dir_storage: DirStorage<u32> = DirStorage::restore_from_bytes(entries).unwrap();
+    assert_eq!(*dir_storage.get(".hidden").unwrap(), 1);
+    assert_eq!(*dir_storage.get("two").unwrap(), 2);
+
+    let entries = vec![("bad".to_string(), b"x".to_vec())];
+    match DirStorage::<u32>::restore_from_bytes(entries) {
+        Err(Error::RestoreError(key, _)) => assert_eq!(key, "bad"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_permissions() {
+    use soter::dir::Options;
+    use std::fs::{metadata, set_permissions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let path = dir.path().join("config");
+
+    let options = Options::default().atomic_writes(true).preserve_permissions(true);
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(Default::default(), options);
+    dir_storage.insert("config", 1);
+    dir_storage.store_single(dir_str, "config").unwrap();
+    set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+
+    dir_storage.insert("config", 2);
+    dir_storage.store_single(dir_str, "config").unwrap();
+    assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+    dir_storage.set_options(Options::default().preserve_permissions(true));
+    dir_storage.store_single(dir_str, "config").unwrap();
+    assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+}
+
+#[test]
+fn restore_shared() {
+    use soter::dir::Options;
+    use std::rc::Rc;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<String> = DirStorage::default();
+    dir_storage.insert("a", "same".to_string());
+    dir_storage.insert("b", "same".to_string());
+    dir_storage.insert("c", "other".to_string());
+    dir_storage.store(dir_str).unwrap();
+    std::fs::hard_link(dir.path().join("c"), dir.path().join("d")).unwrap();
+
+    let restored: DirStorage<Rc<String>> = DirStorage::restore_shared(dir_str, Options::default()).unwrap();
+    assert!(Rc::ptr_eq(restored.get("a").unwrap(), restored.get("b").unwrap()));
+    assert!(Rc::ptr_eq(restored.get("c").unwrap(), restored.get("d").unwrap()));
+    assert!(!Rc::ptr_eq(restored.get("a").unwrap(), restored.get("c").unwrap()));
+    assert_eq!(**restored.get("d").unwrap(), "other");
+
+    // Items are restored with their directory, like restore_with_options does.
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let mut side_files: DirStorage<SideFile> = DirStorage::default();
+    for key in &["a", "b"] {
+        let blob = SideFile {
+            name: "blob".to_string(),
+            contents: "large contents".to_string(),
+        };
+        side_files.insert(*key, blob);
+    }
+    side_files.store(dir_str).unwrap();
+
+    let restored: DirStorage<Rc<SideFile>> = DirStorage::restore_shared(dir_str, Options::default()).unwrap();
+    assert!(Rc::ptr_eq(restored.get("a").unwrap(), restored.get("b").unwrap()));
+    assert_eq!(restored.get("a").unwrap().contents, "large contents");
+    assert_eq!(restored.stats().files_read, 2);
+}
+
+#[test]
+fn value_diff() {
+    let mut a: DirStorage<u32> = DirStorage::default();
+    a.insert("same", 1);
+    a.insert("changed", 2);
+    a.insert("only_a", 3);
+    let mut b: DirStorage<u32> = DirStorage::default();
+    b.insert("same", 1);
+    b.insert("changed", 5);
+    b.insert("only_b", 4);
+
+    let diff = a.value_diff(&b);
+    assert!(!diff.is_empty());
+    assert_eq!(diff.only_in_self, vec![(&"only_a".to_string(), &3)]);
+    assert_eq!(diff.only_in_other, vec![(&"only_b".to_string(), &4)]);
+    assert_eq!(diff.changed, vec![(&"changed".to_string(), &2, &5)]);
+    assert!(a.value_diff(&a).is_empty());
+}
+
+#[test]
+fn store_with_mode() {
+    use soter::dir::CommitMode;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("a", 1);
+    dir_storage.insert("b", 2);
+    dir_storage.store_with_mode(dir_str, CommitMode::PerFile).unwrap();
+
+    dir_storage.insert("a", 10);
+    dir_storage.insert("b", 20);
+    dir_storage.insert("sub", 30);
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    match dir_storage.store_with_mode(dir_str, CommitMode::Transactional) {
+        Err(Error::PathIsDirectory(key)) => assert_eq!(key, "sub"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(*restored.get("a").unwrap(), 1);
+    assert_eq!(*restored.get("b").unwrap(), 2);
+    let names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".tmp"))
+        .collect();
+    assert!(names.is_empty());
+
+    std::fs::remove_dir(dir.path().join("sub")).unwrap();
+    dir_storage.store_with_mode(dir_str, CommitMode::Transactional).unwrap();
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+}
+
+#[test]
+fn unit_values() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<()> = DirStorage::default();
+    dir_storage.insert("member", ());
+    dir_storage.store(dir_str).unwrap();
+    assert_eq!(std::fs::metadata(dir.path().join("member")).unwrap().len(), 0);
+    std::fs::write(dir.path().join("edited"), "not empty").unwrap();
+
+    let restored: DirStorage<()> = DirStorage::restore(dir_str).unwrap();
+    assert!(restored.contains_key("member"));
+    assert!(restored.contains_key("edited"));
+}
+
+#[test]
+fn scan_prefix() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("user_1", 1);
+    dir_storage.insert("user_2", 2);
+    dir_storage.insert("group_1", 3);
+    dir_storage.store(dir_str).unwrap();
+
+    let scanner: DirStorage<u32> = DirStorage::default();
+    let mut users: Vec<(String, u32)> = scanner
+        .scan_prefix(dir_str, "user_")
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    users.sort();
+    assert_eq!(users, vec![("user_1".to_string(), 1), ("user_2".to_string(), 2)]);
+    assert!(scanner.get("user_1").is_none());
+    assert_eq!(scanner.stats().files_read, 2);
+}
+
+#[test]
+fn framing() {
+    use soter::dir::{Framing, Options};
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let options = Options::default().framing(Framing::new(&b"MAGIC\n"[..], &b"\nEND"[..]));
+    let mut dir_storage: DirStorage<String> = DirStorage::with_options(Default::default(), options.clone());
+    dir_storage.insert("a", "payload".to_string());
+    dir_storage.store(dir_str).unwrap();
+    assert_eq!(std::fs::read(dir.path().join("a")).unwrap(), b"MAGIC\npayload\nEND");
+
+    let restored: DirStorage<String> = DirStorage::restore_with_options(dir_str, options.clone()).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    std::fs::write(dir.path().join("a"), "OTHER\npayload\nEND").unwrap();
+    match DirStorage::<String>::restore_with_options(dir_str, options) {
+        Err(Error::RestoreError(_, message)) => assert_eq!(message, "header does not match"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let unchecked = Options::default().framing(Framing::new(&b"MAGIC\n"[..], &b"\nEND"[..]).verify(false));
+    let restored: DirStorage<String> = DirStorage::restore_with_options(dir_str, unchecked.clone()).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    std::fs::write(dir.path().join("a"), "short").unwrap();
+    assert!(DirStorage::<String>::restore_with_options(dir_str, unchecked).is_err());
+}
+
+#[test]
+fn entry_or_restore() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("on_disk"), "7").unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    *dir_storage.entry("on_disk").or_restore(dir_str).unwrap() += 1;
+    *dir_storage.entry("on_disk").or_restore(dir_str).unwrap() += 1;
+    assert_eq!(*dir_storage.get("on_disk").unwrap(), 9);
+    assert_eq!(*dir_storage.entry("missing").or_restore(dir_str).unwrap(), 0);
+    assert_eq!(*dir_storage.entry("missing").or_insert(5), 0);
+    assert_eq!(*dir_storage.entry("new").or_insert(5), 5);
+
+    std::fs::write(dir.path().join("bad"), "x").unwrap();
+    assert!(dir_storage.entry("bad").or_restore(dir_str).is_err());
+    assert!(!dir_storage.contains_key("bad"));
+}
+
+#[test]
+fn rebalance_shards() {
+    use soter::dir::{Options, ShardScheme};
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("a", 1);
+    dir_storage.insert("ab", 2);
+    dir_storage.insert("abc", 3);
+    dir_storage.store(dir_str).unwrap();
+
+    let moves = dir_storage.rebalance_shards(dir_str, ShardScheme::Prefix(1), true).unwrap();
+    let keys: Vec<&str> = moves.iter().map(|m| m.key.as_str()).collect();
+    assert_eq!(keys, vec!["a", "ab", "abc"]);
+    assert!(dir.path().join("ab").is_file());
+
+    dir_storage.rebalance_shards(dir_str, ShardScheme::Prefix(1), false).unwrap();
+    assert!(dir.path().join("a").join("a").is_file());
+    assert!(dir.path().join("a").join("ab").is_file());
+    assert!(dir.path().join("a").join("abc").is_file());
+
+    let moves = dir_storage.rebalance_shards(dir_str, ShardScheme::Prefix(2), false).unwrap();
+    assert_eq!(moves.len(), 2);
+    assert!(dir.path().join("a").join("a").is_file());
+    assert!(dir.path().join("ab").join("ab").is_file());
+    assert!(ShardScheme::Prefix(2).path_for(dir.path(), "abc").is_file());
+    let sharded = Options::default().shards(ShardScheme::Prefix(2));
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(dir_str, sharded).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    dir_storage.rebalance_shards(dir_str, ShardScheme::Flat, false).unwrap();
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    std::fs::create_dir(dir.path().join("x")).unwrap();
+    std::fs::write(dir.path().join("x").join("a"), "1").unwrap();
+    match dir_storage.rebalance_shards(dir_str, ShardScheme::Flat, false) {
+        Err(Error::KeyCollision(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn store_sharded() {
+    use soter::dir::{NameHasher, Options, Recursion, ShardScheme};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let hasher = NameHasher::default();
+    let options = Options::default().shards(ShardScheme::Prefix(1)).backup_on_overwrite(1);
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("ab", 1);
+    dir_storage.insert("b", 2);
+    dir_storage.store(dir_str).unwrap();
+    assert!(dir.path().join("a").join("ab").is_file());
+    assert!(dir.path().join("b").join("b").is_file());
+    std::fs::write(dir.path().join("top"), "3").unwrap();
+
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(dir_str, options.clone()).unwrap();
+    assert_eq!(restored, dir_storage);
+    let mut single: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    single.restore_single(dir_str, "ab").unwrap();
+    assert_eq!(*single.get("ab").unwrap(), 1);
+    assert!(matches!(
+        DirStorage::<u32>::restore_recursive(dir_str, options.clone(), Recursion::default()),
+        Err(Error::Unsupported(_))
+    ));
+
+    dir_storage.store_single(dir_str, "ab").unwrap();
+    std::fs::remove_file(dir.path().join("a").join("ab")).unwrap();
+    let orphans = dir_storage.purge_orphans(dir_str, false).unwrap();
+    assert_eq!(orphans.len(), 1);
+    assert!(orphans[0].starts_with(dir.path().join("a")));
+
+    std::fs::write(dir.path().join(".ab.rebalance"), "4").unwrap();
+    assert_eq!(dir_storage.recover(dir_str).unwrap(), vec![".ab.rebalance".to_string()]);
+    assert_eq!(std::fs::read_to_string(dir.path().join("a").join("ab")).unwrap(), "4");
+
+    let hashed_dir = TempDir::new("soter_test").unwrap();
+    let hashed_str = hashed_dir.path().to_str().unwrap();
+    let hashed = Options::default().shards(ShardScheme::Prefix(2)).name_hasher(hasher);
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), hashed.clone());
+    dir_storage.insert("ab", 1);
+    dir_storage.store(hashed_str).unwrap();
+    assert!(ShardScheme::Prefix(2).path_for(hashed_dir.path(), &hasher.hash("ab")).is_file());
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(hashed_str, hashed).unwrap();
+    assert_eq!(restored, dir_storage);
+}
+
+#[test]
+fn compare_and_swap() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut a: DirStorage<String> = DirStorage::default();
+    let mut b: DirStorage<String> = DirStorage::default();
+    assert!(!a.compare_and_swap(dir_str, "state", &"idle".to_string(), "busy".to_string()).unwrap());
+
+    a.insert("state", "idle".to_string());
+    a.store_single(dir_str, "state").unwrap();
+    assert!(b.compare_and_swap(dir_str, "state", &"idle".to_string(), "busy".to_string()).unwrap());
+    assert!(!a.compare_and_swap(dir_str, "state", &"idle".to_string(), "other".to_string()).unwrap());
+    assert_eq!(a.get("state").unwrap(), "busy");
+    assert_eq!(std::fs::read_to_string(dir.path().join("state")).unwrap(), "busy");
+}
+
+#[test]
+fn restore_map() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.insert("a", 1);
+    dir_storage.insert("b", 2);
+    dir_storage.store(dir_str).unwrap();
+
+    let restored: DirStorage<u32> = DirStorage::restore_map(dir_str, |_, v| Ok(v * 10)).unwrap();
+    assert_eq!(*restored.get("a").unwrap(), 10);
+    assert_eq!(*restored.get("b").unwrap(), 20);
+
+    let result = DirStorage::<u32>::restore_map(dir_str, |key, v| {
+        if v > 1 {
+            Err(Error::RestoreError(key, "too large".to_string()))
+        } else {
+            Ok(v)
+        }
+    });
+    match result {
+        Err(Error::RestoreError(key, _)) => assert_eq!(key, "b"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn windows_key_encoding() {
+    use soter::dir::{KeyEncoding, Options};
+
+    let encoding = KeyEncoding::Windows;
+    assert_eq!(encoding.encode("Con: a."), "%43on%3A a%2E");
+    assert_eq!(encoding.encode("nul.txt"), "%6Eul.txt");
+    assert_eq!(encoding.encode("console"), "console");
+    assert_eq!(encoding.encode("100%"), "100%25");
+    assert_eq!(encoding.decode("%43on%3A a%2E"), "Con: a.");
+    assert_eq!(encoding.decode("bad%zz"), "bad%zz");
+    assert_eq!(KeyEncoding::Plain.encode("CON"), "CON");
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().key_encoding(KeyEncoding::Windows);
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(Default::default(), options.clone());
+    dir_storage.insert("Key", 1);
+    dir_storage.insert("key", 2);
+    dir_storage.insert("a/b", 3);
+    dir_storage.store(dir_str).unwrap();
+    assert!(dir.path().join("%4Bey").is_file());
+    assert!(dir.path().join("a%2Fb").is_file());
+
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(dir_str, options).unwrap();
+    assert_eq!(restored, dir_storage);
+}
+
+#[test]
+fn single_file() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let path = dir.path().join("store.soter");
+
+    let mut dir_storage: DirStorage<String> = DirStorage::default();
+    dir_storage.insert("a", "first".to_string());
+    dir_storage.insert("b", "second".to_string());
+    dir_storage.store_to_single_file(&path).unwrap();
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    let restored: DirStorage<String> = DirStorage::restore_from_single_file(&path).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(restored.stats().files_read, 1);
+
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &bytes[..bytes.len() - 2]).unwrap();
+    match DirStorage::<String>::restore_from_single_file(&path) {
+        Err(Error::RestoreError(record, _)) => assert_eq!(record, "record 1"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn backup_on_overwrite() {
+    use soter::dir::Options;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let options = Options::default().backup_on_overwrite(2);
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(Default::default(), options.clone());
+    dir_storage.insert("a.b", 0);
+    dir_storage.store(dir_str).unwrap();
+    for i in 1..=3 {
+        dir_storage.insert("a", i);
+        dir_storage.store_single(dir_str, "a").unwrap();
+        dir_storage.store_single(dir_str, "a.b").unwrap();
+    }
+
+    let backup_dir = dir.path().join(".bak");
+    let mut backups: Vec<String> = std::fs::read_dir(&backup_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !name.starts_with("a.b."))
+        .collect();
+    backups.sort();
+    let contents: Vec<String> = backups
+        .iter()
+        .map(|name| std::fs::read_to_string(backup_dir.join(name)).unwrap())
+        .collect();
+    assert_eq!(contents, vec!["1", "2"]);
+
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(dir_str, options).unwrap();
+    assert_eq!(restored, dir_storage);
+}
+
+#[test]
+fn restore_multi_format() {
+    use soter::dir::{Detector, MultiFormat, Options, ShardScheme, Unrecognized};
+
+    /// A number stored in binary, as four little-endian bytes after a magic byte.
+    struct Binary(u32);
+
+    impl From<Binary> for u32 {
+        fn from(binary: Binary) -> u32 {
+            binary.0
+        }
+    }
+
+    impl<W: Write, R: Read> Storable<W, R> for Binary {
+        fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+            let mut bytes = [0; 5];
+            reader
+                .read_exact(&mut bytes)
+                .map_err(|e| StorableRestoreError(e.to_string()))?;
+            Ok(Binary(u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]])))
+        }
+
+        fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+            writer
+                .write_all(&[0xff])
+                .and_then(|_| writer.write_all(&self.0.to_le_bytes()))
+                .map_err(|e| StorableStoreError(e.to_string()))
+        }
+    }
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "1").unwrap();
+    std::fs::write(dir.path().join("b"), [0xff, 2, 0, 0, 0]).unwrap();
+    std::fs::write(dir.path().join("c"), "other").unwrap();
+
+    let formats = MultiFormat::new()
+        .format::<u32>(Detector::Extension("txt".to_string()))
+        .format::<Binary>(Detector::Magic(vec![0xff]));
+    assert!(DirStorage::restore_multi_format(dir_str, Options::default(), &formats).is_err());
+
+    let formats = formats.unrecognized(Unrecognized::Skip);
+    let restored: DirStorage<u32> = DirStorage::restore_multi_format(dir_str, Options::default(), &formats).unwrap();
+    assert_eq!(*restored.get("a.txt").unwrap(), 1);
+    assert_eq!(*restored.get("b").unwrap(), 2);
+    assert!(!restored.contains_key("c"));
+
+    // The files are found following the options.
+    let sharded_dir = TempDir::new("soter_test").unwrap();
+    let sharded = Options::default().shards(ShardScheme::Prefix(1));
+    std::fs::create_dir(sharded_dir.path().join("a")).unwrap();
+    std::fs::rename(dir.path().join("a.txt"), sharded_dir.path().join("a").join("a.txt")).unwrap();
+    let sharded_str = sharded_dir.path().to_str().unwrap();
+    let restored: DirStorage<u32> = DirStorage::restore_multi_format(sharded_str, sharded.clone(), &formats).unwrap();
+    assert_eq!(*restored.get("a.txt").unwrap(), 1);
+    assert_eq!(restored.options(), &sharded);
+}
+
+#[test]
+fn shrink() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "  42 \n\n").unwrap();
+    std::fs::write(dir.path().join("b"), "7").unwrap();
+
+    let dir_storage: DirStorage<u32> = DirStorage::default();
+    let report = dir_storage.shrink(dir_str).unwrap();
+    assert_eq!(report.files, 2);
+    assert_eq!(report.bytes_before, 8);
+    assert_eq!(report.bytes_after, 3);
+    assert_eq!(report.reclaimed(), 5);
+    assert_eq!(std::fs::read_to_string(dir.path().join("a")).unwrap(), "42");
+}
+
+#[test]
+fn iter_files_on_disk() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "1").unwrap();
+    std::fs::write(dir.path().join("b"), "not a number").unwrap();
+    std::fs::write(dir.path().join(".hidden"), "2").unwrap();
+    std::fs::create_dir(dir.path().join("subdir")).unwrap();
+
+    let dir_storage: DirStorage<u32> = DirStorage::default();
+    let mut files: Vec<_> = dir_storage
+        .iter_files_on_disk(dir_str)
+        .unwrap()
+        .map(|file| file.unwrap())
+        .collect();
+    files.sort_by(|a, b| a.key.cmp(&b.key));
+    let keys: Vec<&str> = files.iter().map(|file| file.key.as_str()).collect();
+    assert_eq!(keys, vec!["a", "b"]);
+    assert_eq!(files[0].path, dir.path().join("a"));
+    assert_eq!(files[0].len, 1);
+    assert_eq!(files[1].len, 12);
+    assert!(files[1].modified <= std::time::SystemTime::now());
+    assert_eq!(dir_storage.stats().files_read, 0);
+}
+
+#[test]
+fn subscribe() {
+    use soter::dir::StoreEvent;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.subscribe(move |event| {
+        let _ = sender.send(event.clone());
+    });
+    dir_storage.insert("a", 1);
+    dir_storage.insert("a", 2);
+    dir_storage.entry("b").or_insert(3);
+    dir_storage.store_single(dir_str, "a").unwrap();
+    assert!(dir_storage.store_single(dir_str, "c").is_err());
+    assert_eq!(dir_storage.remove("b"), Some(3));
+    assert_eq!(dir_storage.remove("b"), None);
+    dir_storage.compact_keys(dir_str, |k| k.to_uppercase()).unwrap();
+    dir_storage.compact_keys(dir_str, |k| k.to_string()).unwrap();
+
+    let events: Vec<StoreEvent> = receiver.try_iter().collect();
+    assert_eq!(
+        events,
+        vec![
+            StoreEvent::Inserted { key: "a".to_string(), replaced: false },
+            StoreEvent::Inserted { key: "a".to_string(), replaced: true },
+            StoreEvent::Inserted { key: "b".to_string(), replaced: false },
+            StoreEvent::Stored { key: "a".to_string() },
+            StoreEvent::Removed { key: "b".to_string() },
+            StoreEvent::Renamed { from: "a".to_string(), to: "A".to_string() },
+        ]
+    );
+
+    drop(dir_storage);
+    let restored: DirStorage<u32> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored.get("A"), Some(&2));
+}
+
+#[test]
+fn find_on_disk() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    for i in 0..10 {
+        dir_storage.insert(format!("k{}", i), i);
+    }
+    dir_storage.store(dir_str).unwrap();
+
+    let reader: DirStorage<u32> = DirStorage::default();
+    let found = reader.find_on_disk(dir_str, |_, &value| value == 7).unwrap();
+    assert_eq!(found, Some(("k7".to_string(), 7)));
+    assert!(reader.get("k7").is_none());
+    assert!(reader.find_on_disk(dir_str, |_, &value| value > 100).unwrap().is_none());
+
+    reader.reset_stats();
+    assert!(reader.find_on_disk(dir_str, |_, _| true).unwrap().is_some());
+    assert_eq!(reader.stats().files_read, 1);
+}
+
+#[test]
+fn name_hasher() {
+    use soter::dir::{NameHasher, Options};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().name_hasher(NameHasher::default());
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("secret/key", 1);
+    dir_storage.insert("other", 2);
+    dir_storage.store(dir_str).unwrap();
+    let name = NameHasher::default().hash("secret/key");
+    assert_eq!(name, NameHasher::default().hash("secret/key"));
+    assert_eq!(std::fs::read_to_string(dir.path().join(&name)).unwrap(), "1");
+    assert!(!dir.path().join("other").exists());
+
+    let restored: DirStorage<u32> = DirStorage::restore_with_options(dir_str, options.clone()).unwrap();
+    assert_eq!(restored, dir_storage);
+    let mut single: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    assert!(single.restore_single(dir_str, "other").unwrap());
+    assert_eq!(single.get("other"), Some(&2));
+
+    let collide = Options::default().name_hasher(NameHasher::new(|_| "same".to_string()));
+    let mut colliding: DirStorage<u32> = DirStorage::with_options(HashMap::new(), collide);
+    colliding.insert("a", 1);
+    colliding.insert("b", 2);
+    let manifest = std::fs::read(dir.path().join(".soter_manifest")).unwrap();
+    match colliding.store(dir_str) {
+        Err(Error::KeyCollision(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // The keys of a store are recorded all at once, or not at all.
+    assert_eq!(std::fs::read(dir.path().join(".soter_manifest")).unwrap(), manifest);
+    assert!(!dir.path().join("same").exists());
+
+    std::fs::write(dir.path().join("stray"), "3").unwrap();
+    assert!(DirStorage::<u32>::restore_with_options(dir_str, options).is_err());
+}
+
+#[test]
+fn restore_resumable() {
+    use soter::dir::RestoreCheckpoint;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    for i in 0..5 {
+        std::fs::write(dir.path().join(format!("k{}", i)), i.to_string()).unwrap();
+    }
+    std::fs::write(dir.path().join("bad"), "not a number").unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    let mut checkpoint = RestoreCheckpoint::new();
+    assert!(dir_storage.restore_resumable(dir_str, &mut checkpoint).is_err());
+    assert!(!checkpoint.is_done("bad"));
+    let done = checkpoint.len();
+
+    std::fs::write(dir.path().join("bad"), "5").unwrap();
+    let mut keys: Vec<String> = checkpoint.keys().map(String::from).collect();
+    let mut checkpoint: RestoreCheckpoint = keys.clone().into_iter().collect();
+    dir_storage.reset_stats();
+    dir_storage.restore_resumable(dir_str, &mut checkpoint).unwrap();
+    assert_eq!(dir_storage.stats().files_read as usize, 6 - done);
+    assert_eq!(checkpoint.len(), 6);
+
+    keys.push("bad".to_string());
+    for key in &keys {
+        assert!(dir_storage.get(key.as_str()).is_some());
+    }
+    assert_eq!(dir_storage.get("bad"), Some(&5));
+}
+
+/// A value kept in a hidden side file, whose name only is stored in the item file.
+#[derive(Debug, PartialEq)]
+struct SideFile {
+    name: String,
+    contents: String,
+}
+
+impl<W: Write, R: Read> Storable<W, R> for SideFile {
+    fn restore(_reader: R) -> Result<Self, StorableRestoreError> {
+        Err(StorableRestoreError("needs a directory".to_string()))
+    }
+
+    fn store(&self, _writer: W) -> Result<(), StorableStoreError> {
+        Err(StorableStoreError("needs a directory".to_string()))
+    }
+
+    fn restore_from_dir(reader: R, dir: &std::path::Path) -> Result<Self, StorableRestoreError> {
+        let name: String = Storable::<W, R>::restore(reader)?;
+        let contents = std::fs::read_to_string(dir.join(format!(".{}", name)))
+            .map_err(|e| StorableRestoreError(e.to_string()))?;
+        Ok(SideFile { name, contents })
+    }
+
+    fn store_in_dir(&self, writer: W, dir: &std::path::Path) -> Result<(), StorableStoreError> {
+        std::fs::write(dir.join(format!(".{}", self.name)), &self.contents)
+            .map_err(|e| StorableStoreError(e.to_string()))?;
+        Storable::<W, R>::store(&self.name, writer)
+    }
+}
+
+#[test]
+fn store_in_dir() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<SideFile> = DirStorage::default();
+    let blob = SideFile {
+        name: "blob".to_string(),
+        contents: "large contents".to_string(),
+    };
+    dir_storage.insert("a", blob);
+    dir_storage.store(dir_str).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("a")).unwrap(), "blob");
+    assert_eq!(std::fs::read_to_string(dir.path().join(".blob")).unwrap(), "large contents");
+
+    let restored: DirStorage<SideFile> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+}
+
+#[test]
+fn purge_orphans() {
+    use soter::dir::Options;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    dir_storage.set_options(Options::default().backup_on_overwrite(2));
+    dir_storage.insert("kept", 1);
+    dir_storage.insert("gone", 2);
+    dir_storage.store(dir_str).unwrap();
+    dir_storage.store(dir_str).unwrap();
+    std::fs::remove_file(dir.path().join("gone")).unwrap();
+
+    let backup_dir = dir.path().join(".bak");
+    let orphans = dir_storage.purge_orphans(dir_str, true).unwrap();
+    assert_eq!(orphans.len(), 1);
+    assert_eq!(orphans[0].parent().unwrap(), backup_dir);
+    assert!(orphans[0].file_name().unwrap().to_str().unwrap().starts_with("gone."));
+    assert!(orphans[0].exists());
+
+    assert_eq!(dir_storage.purge_orphans(dir_str, false).unwrap(), orphans);
+    assert!(!orphans[0].exists());
+    assert_eq!(std::fs::read_dir(&backup_dir).unwrap().count(), 1);
+
+    std::fs::remove_file(dir.path().join("kept")).unwrap();
+    assert_eq!(dir_storage.purge_orphans(dir_str, false).unwrap().len(), 1);
+    assert!(!backup_dir.exists());
+}
+
+#[test]
+fn loaded_and_total_len() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "1").unwrap();
+    std::fs::write(dir.path().join("b"), "2").unwrap();
+
+    let mut dir_storage: DirStorage<u32> = DirStorage::default();
+    assert!(dir_storage.restore_single(dir_str, "a").unwrap());
+    dir_storage.insert("new", 3);
+    assert_eq!(dir_storage.loaded_len(), 2);
+    assert_eq!(dir_storage.total_len(dir_str).unwrap(), 2);
+    dir_storage.store(dir_str).unwrap();
+    assert_eq!(dir_storage.total_len(dir_str).unwrap(), 3);
+}
+
+/// A value whose file, once restored, removes the file `victim` and adds the file `late`.
+#[derive(Debug, PartialEq)]
+struct Trigger(String);
+
+impl<W: Write, R: Read> Storable<W, R> for Trigger {
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        Storable::<W, R>::restore(reader).map(Trigger)
+    }
+
+    fn store(&self, writer: W) -> Result<(), StorableStoreError> {
+        Storable::<W, R>::store(&self.0, writer)
+    }
+
+    fn restore_from_dir(reader: R, dir: &std::path::Path) -> Result<Self, StorableRestoreError> {
+        let trigger: Trigger = Storable::<W, R>::restore(reader)?;
+        if trigger.0 == "trigger" {
+            let _ = std::fs::remove_file(dir.join("victim"));
+            std::fs::write(dir.join("late"), "late").map_err(|e| StorableRestoreError(e.to_string()))?;
+        }
+        Ok(trigger)
+    }
+}
+
+#[test]
+fn restore_with_listing() {
+    use soter::dir::{Options, RestoreListing};
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let reset = || {
+        let _ = std::fs::remove_file(dir.path().join("late"));
+        std::fs::write(dir.path().join("trigger"), "trigger").unwrap();
+        std::fs::write(dir.path().join("victim"), "victim").unwrap();
+    };
+
+    reset();
+    let listing = RestoreListing::default().skip_vanished(true);
+    let restored: DirStorage<Trigger> =
+        DirStorage::restore_with_listing(dir_str, Options::default(), listing).unwrap();
+    assert!(restored.contains_key("trigger"));
+    assert!(!restored.contains_key("late"));
+    assert!(dir.path().join("late").exists());
+
+    reset();
+    let restored: DirStorage<Trigger> =
+        DirStorage::restore_with_listing(dir_str, Options::default(), listing.rescan(true)).unwrap();
+    assert_eq!(restored.get("late"), Some(&Trigger("late".to_string())));
+}
+
+/// A value with a field that is not stored.
+#[derive(Debug, PartialEq)]
+struct WithCache {
+    value: u32,
+    hits: u32,
+}
+
+impl<W: Write, R: Read> Storable<W, R> for WithCache {
+    fn restore(reader: R) -> Result<Self, StorableRestoreError> {
+        Storable::<W, R>::restore(reader).map(|value| WithCache { value, hits: 0 })
+    }
+
+    fn store(&self, writer: W) -> Result<(), StorableStoreError> {
+        Storable::<W, R>::store(&self.value, writer)
+    }
+}
+
+#[test]
+fn storable_equality() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let a = WithCache { value: 1, hits: 5 };
+    assert!(storable_eq(&a, &WithCache { value: 1, hits: 0 }));
+    assert!(!storable_eq(&a, &WithCache { value: 2, hits: 5 }));
+
+    let mut dir_storage: DirStorage<WithCache> = DirStorage::default();
+    dir_storage.insert("a", a);
+    dir_storage.set_verify_after_write(true);
+    assert!(matches!(dir_storage.store(dir_str), Err(Error::VerificationFailed(_))));
+    dir_storage.set_verify_after_write_with(Some(storable_eq));
+    dir_storage.store(dir_str).unwrap();
+
+    let restored: DirStorage<WithCache> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored.value_diff(&dir_storage).changed.len(), 1);
+    assert!(restored.value_diff_by(&dir_storage, storable_eq).is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn restore_classified() {
+    use soter::dir::{ErrorAction, Options};
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "1").unwrap();
+    let target = dir.path().join(".target");
+    std::os::unix::fs::symlink(&target, dir.path().join("b")).unwrap();
+
+    let failed: Result<DirStorage<u32>, _> =
+        DirStorage::restore_classified(dir_str, Options::default(), |_, _, _| ErrorAction::Fail);
+    assert!(failed.is_err());
+
+    let restored: DirStorage<u32> =
+        DirStorage::restore_classified(dir_str, Options::default(), |_, _, _| ErrorAction::Skip).unwrap();
+    assert_eq!(restored.get("a"), Some(&1));
+    assert!(!restored.contains_key("b"));
+
+    let mut attempts = Vec::new();
+    let restored: DirStorage<u32> = DirStorage::restore_classified(dir_str, Options::default(), |e, path, attempt| {
+        assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(path, dir.path());
+        attempts.push(attempt);
+        if attempt == 2 {
+            std::fs::write(&target, "2").unwrap();
+        }
+        ErrorAction::Retry
+    })
+    .unwrap();
+    assert_eq!(attempts, vec![1, 2]);
+    assert_eq!(restored.get("b"), Some(&2));
+}
+
+#[cfg(unix)]
+#[test]
+fn restore_classified_unreadable_shard() {
+    use soter::dir::{ErrorAction, Options, ShardScheme};
+    use std::collections::HashMap;
+    use std::fs::{read_dir, set_permissions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().shards(ShardScheme::Prefix(1));
+    let mut dir_storage: DirStorage<u32> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("a", 1);
+    dir_storage.insert("b", 2);
+    dir_storage.insert("c", 3);
+    dir_storage.insert("d", 4);
+    dir_storage.store(dir_str).unwrap();
+
+    // Two shards, so that one of them is not the last listed.
+    let shards = [dir.path().join("b"), dir.path().join("c")];
+    let chmod = |mode| shards.iter().for_each(|shard| set_permissions(shard, Permissions::from_mode(mode)).unwrap());
+    chmod(0o000);
+    // Nothing to check where permissions do not apply, e.g. when running as root.
+    if read_dir(&shards[0]).is_ok() {
+        chmod(0o755);
+        return;
+    }
+    let mut errors = 0;
+    let restored: Result<DirStorage<u32>, _> = DirStorage::restore_classified(dir_str, options, |e, _, _| {
+        assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied);
+        errors += 1;
+        ErrorAction::Skip
+    });
+    chmod(0o755);
+    let restored = restored.unwrap();
+    assert_eq!(errors, 2);
+    assert_eq!(restored.get("a"), Some(&1));
+    assert_eq!(restored.get("d"), Some(&4));
+    assert!(!restored.contains_key("b") && !restored.contains_key("c"));
+}
+
+#[test]
+fn store_parts() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<String> = DirStorage::default();
+    dir_storage.insert("big", "a value split across files".to_string());
+    dir_storage.store_parts(dir_str, "big", 4).unwrap();
+    assert!(dir.path().join(".big.part3").is_file());
+    assert!(!dir.path().join("big").exists());
+    assert_eq!(DirStorage::<String>::restore(dir_str).unwrap().loaded_len(), 0);
+
+    let mut restored: DirStorage<String> = DirStorage::default();
+    assert!(!restored.restore_parts(dir_str, "missing").unwrap());
+    assert!(restored.restore_parts(dir_str, "big").unwrap());
+    assert_eq!(restored, dir_storage);
+
+    dir_storage.store_parts(dir_str, "big", 2).unwrap();
+    assert!(!dir.path().join(".big.part2").exists());
+    assert!(restored.restore_parts(dir_str, "big").unwrap());
+    assert_eq!(restored, dir_storage);
+
+    let part = std::fs::read(dir.path().join(".big.part1")).unwrap();
+    std::fs::remove_file(dir.path().join(".big.part1")).unwrap();
+    let error = restored.restore_parts(dir_str, "big").unwrap_err().to_string();
+    assert!(error.ends_with("missing part 1 of 2"), "{}", error);
+
+    std::fs::write(dir.path().join(".big.part1"), &part).unwrap();
+    let mut extra = part;
+    extra[0] = 2;
+    std::fs::write(dir.path().join(".big.part2"), &extra).unwrap();
+    let error = restored.restore_parts(dir_str, "big").unwrap_err().to_string();
+    assert!(error.ends_with("unexpected part 2 of 2"), "{}", error);
+
+    dir_storage.insert("other", "kept".to_string());
+    dir_storage.store_parts(dir_str, "other", 2).unwrap();
+    let orphans = dir_storage.purge_orphans(dir_str, true).unwrap();
+    let expected: Vec<_> = (0..3).map(|index| dir.path().join(format!(".big.part{}", index))).collect();
+    assert_eq!(orphans, expected);
+    let mut recovered = dir_storage.recover(dir_str).unwrap();
+    recovered.sort();
+    assert_eq!(recovered, vec![".big.part0", ".big.part1", ".big.part2"]);
+    assert!(!dir.path().join(".big.part0").exists());
+    assert!(restored.restore_parts(dir_str, "other").unwrap());
+    assert!(dir_storage.purge_orphans(dir_str, false).unwrap().is_empty());
+
+    match dir_storage.store_parts(dir_str, "big", 0) {
+        Err(Error::StoreError(_, message)) => assert_eq!(message, "an item needs at least one part"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn store_reports_last_flush() {
+    use soter::dir::Options;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::os::unix::fs::symlink("/dev/full", dir.path().join("full")).unwrap();
+
+    let mut dir_storage = DirStorage::with_options(Default::default(), Options::default().atomic_writes(false));
+    dir_storage.insert("full", "lost".to_string());
+    match dir_storage.store_single(dir_str, "full") {
+        Err(Error::IOError(e)) => assert_eq!(e.raw_os_error(), Some(28)),
+        result => panic!("expected the flush to fail, got {:?}", result),
+    }
+}
+
+#[test]
+fn store_single_stream() {
+    use soter::adaptors::Records;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let dir_storage: DirStorage<Records<u32>> = DirStorage::default();
+    dir_storage.store_single_stream(dir_str, "numbers", 1..=1000).unwrap();
+    assert!(!dir_storage.contains_key("numbers"));
+
+    let mut sum = 0;
+    assert!(dir_storage.restore_single_stream(dir_str, "numbers", |n| sum += n).unwrap());
+    assert_eq!(sum, 500_500);
+    assert!(!dir_storage.restore_single_stream(dir_str, "missing", |_| panic!()).unwrap());
+
+    let restored: DirStorage<Records<u32>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored.get("numbers").unwrap().0, (1..=1000).collect::<Vec<_>>());
+
+    let mut bytes = std::fs::read(dir.path().join("numbers")).unwrap();
+    bytes.pop();
+    std::fs::write(dir.path().join("numbers"), bytes).unwrap();
+    assert!(dir_storage.restore_single_stream(dir_str, "numbers", |_| ()).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn restore_recursive() {
+    use soter::dir::{Options, Recursion};
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::create_dir_all(dir.path().join("a/b/.hidden")).unwrap();
+    std::fs::write(dir.path().join("top"), "0").unwrap();
+    std::fs::write(dir.path().join("a/one"), "1").unwrap();
+    std::fs::write(dir.path().join("a/b/two"), "2").unwrap();
+    std::fs::write(dir.path().join("a/b/.hidden/three"), "3").unwrap();
+    std::os::unix::fs::symlink(dir.path(), dir.path().join("a/b/loop")).unwrap();
+
+    let restored: DirStorage<u32> =
+        DirStorage::restore_recursive(dir_str, Options::default(), Recursion::default()).unwrap();
+    assert_eq!(restored.loaded_len(), 3);
+    assert_eq!(restored.get("top"), Some(&0));
+    assert_eq!(restored.get("a/one"), Some(&1));
+    assert_eq!(restored.get("a/b/two"), Some(&2));
+
+    let restored: DirStorage<u32> =
+        DirStorage::restore_recursive(dir_str, Options::default(), Recursion::default().max_depth(1)).unwrap();
+    assert!(restored.contains_key("a/one"));
+    assert!(!restored.contains_key("a/b/two"));
+
+    let recursion = Recursion::default().max_depth(1).fail_too_deep(true);
+    let error = DirStorage::<u32>::restore_recursive(dir_str, Options::default(), recursion).unwrap_err();
+    assert!(error.to_string().ends_with("deeper than the maximum depth of 1"), "{}", error);
+}
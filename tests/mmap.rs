@@ -0,0 +1,147 @@
+#![cfg(feature = "mmap")]
+
+use tempdir::TempDir;
+
+use std::io::{Read, Write};
+
+use soter::dir::{DirStorage, StoreEvent};
+use soter::storable::*;
+
+/// A fixed-size record of four little-endian `u32`s.
+#[derive(Debug, PartialEq)]
+struct Record([u32; 4]);
+
+impl<W: Write, R: Read> Storable<W, R> for Record {
+    fn restore(mut reader: R) -> Result<Self, StorableRestoreError> {
+        let mut record = [0; 4];
+        for field in record.iter_mut() {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes).map_err(|e| StorableRestoreError(e.to_string()))?;
+            *field = u32::from_le_bytes(bytes);
+        }
+        Ok(Record(record))
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        for field in self.0.iter() {
+            writer.write_all(&field.to_le_bytes()).map_err(|e| StorableStoreError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn serialized_len(&self) -> Option<u64> {
+        Some(16)
+    }
+}
+
+#[test]
+fn store_single_mapped() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), vec![0xff; 64]).unwrap();
+
+    let mut dir_storage: DirStorage<Record> = DirStorage::default();
+    dir_storage.insert("a", Record([1, 2, 3, 4]));
+    dir_storage.set_verify_after_write(true);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    dir_storage.subscribe(move |event| {
+        let _ = sender.send(event.clone());
+    });
+    dir_storage.store_single_mapped(dir_str, "a").unwrap();
+    assert_eq!(std::fs::metadata(dir.path().join("a")).unwrap().len(), 16);
+    let events: Vec<StoreEvent> = receiver.try_iter().collect();
+    assert_eq!(events, vec![StoreEvent::Stored { key: "a".to_string() }]);
+
+    let restored: DirStorage<Record> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    // Types without a length hint are written the usual way.
+    let mut texts: DirStorage<String> = DirStorage::default();
+    texts.insert("b", "text".to_string());
+    texts.store_single_mapped(dir_str, "b").unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("b")).unwrap(), "text");
+}
+
+/// Claims to be shorter than what it writes, so storing it through a map fails.
+#[derive(Debug, PartialEq)]
+struct Overflowing;
+
+impl<W: Write, R: Read> Storable<W, R> for Overflowing {
+    fn restore(_: R) -> Result<Self, StorableRestoreError> {
+        Ok(Overflowing)
+    }
+
+    fn store(&self, mut writer: W) -> Result<(), StorableStoreError> {
+        writer.write_all(b"too long").map_err(|e| StorableStoreError(e.to_string()))
+    }
+
+    fn serialized_len(&self) -> Option<u64> {
+        Some(2)
+    }
+}
+
+#[test]
+fn store_single_mapped_atomically() {
+    use soter::dir::Options;
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let options = Options::default().atomic_writes(true);
+    std::fs::write(dir.path().join("a"), "old").unwrap();
+    std::fs::hard_link(dir.path().join("a"), dir.path().join("link")).unwrap();
+
+    let mut dir_storage: DirStorage<Record> = DirStorage::with_options(HashMap::new(), options.clone());
+    dir_storage.insert("a", Record([1, 2, 3, 4]));
+    dir_storage.store_single_mapped(dir_str, "a").unwrap();
+    assert_eq!(std::fs::metadata(dir.path().join("a")).unwrap().len(), 16);
+    assert_eq!(std::fs::read_to_string(dir.path().join("link")).unwrap(), "old");
+
+    let mut failing: DirStorage<Overflowing> = DirStorage::with_options(HashMap::new(), options);
+    failing.insert("link", Overflowing);
+    assert!(failing.store_single_mapped(dir_str, "link").is_err());
+    assert_eq!(std::fs::read_to_string(dir.path().join("link")).unwrap(), "old");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn store_single_mapped_backup() {
+    use soter::dir::Options;
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    std::fs::write(dir.path().join("a"), "old").unwrap();
+
+    let options = Options::default().backup_on_overwrite(1);
+    let mut dir_storage: DirStorage<Record> = DirStorage::with_options(HashMap::new(), options);
+    dir_storage.insert("a", Record([1, 2, 3, 4]));
+    dir_storage.store_single_mapped(dir_str, "a").unwrap();
+
+    let backups: Vec<_> = std::fs::read_dir(dir.path().join(".bak")).unwrap().map(Result::unwrap).collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(std::fs::read_to_string(backups[0].path()).unwrap(), "old");
+}
+
+#[cfg(unix)]
+#[test]
+fn store_single_mapped_preserve_permissions() {
+    use soter::dir::Options;
+    use std::collections::HashMap;
+    use std::fs::{metadata, set_permissions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let path = dir.path().join("a");
+    std::fs::write(&path, "old").unwrap();
+    set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+
+    for options in [Options::default(), Options::default().atomic_writes(true)] {
+        let options = options.preserve_permissions(true);
+        let mut dir_storage: DirStorage<Record> = DirStorage::with_options(HashMap::new(), options);
+        dir_storage.insert("a", Record([1, 2, 3, 4]));
+        dir_storage.store_single_mapped(dir_str, "a").unwrap();
+        assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "rmp-serde")]
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use soter::adaptors::MsgPack;
+use soter::dir::DirStorage;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[test]
+fn msgpack() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<MsgPack<Point>> = DirStorage::default();
+    dir_storage.insert("origin", MsgPack(Point { x: 0, y: 0 }));
+    dir_storage.store(dir_str).unwrap();
+    // A fixarray of two positive fixints.
+    assert_eq!(std::fs::read(dir.path().join("origin")).unwrap(), [0x92, 0, 0]);
+
+    let restored: DirStorage<MsgPack<Point>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(restored.get("origin").unwrap().x, 0);
+
+    std::fs::write(dir.path().join("origin"), [0xc1]).unwrap();
+    assert!(DirStorage::<MsgPack<Point>>::restore(dir_str).is_err());
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "prost")]
+
+use tempdir::TempDir;
+
+use soter::adaptors::Protobuf;
+use soter::dir::DirStorage;
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Point {
+    #[prost(int32, tag = "1")]
+    x: i32,
+    #[prost(string, tag = "2")]
+    label: String,
+}
+
+#[test]
+fn protobuf() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Protobuf<Point>> = DirStorage::default();
+    dir_storage.insert("a.pb", Protobuf(Point { x: 150, label: "a".to_string() }));
+    dir_storage.store(dir_str).unwrap();
+    // Field 1 as a varint, then field 2 as a length-delimited string.
+    assert_eq!(std::fs::read(dir.path().join("a.pb")).unwrap(), [0x08, 0x96, 0x01, 0x12, 0x01, b'a']);
+
+    let restored: DirStorage<Protobuf<Point>> = DirStorage::restore(dir_str).unwrap();
+    assert_eq!(restored, dir_storage);
+    assert_eq!(restored.get("a.pb").unwrap().x, 150);
+
+    std::fs::write(dir.path().join("a.pb"), [0x08]).unwrap();
+    assert!(DirStorage::<Protobuf<Point>>::restore(dir_str).is_err());
+}
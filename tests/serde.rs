@@ -0,0 +1,118 @@
+#![cfg(all(feature = "json", feature = "bincode"))]
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use soter::adaptors::{BincodeFormat, JsonFormat, Serde};
+use soter::dir::DirStorage;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[test]
+fn serde_formats() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let json_dir = dir.path().join("json");
+    let bincode_dir = dir.path().join("bincode");
+    std::fs::create_dir(&json_dir).unwrap();
+    std::fs::create_dir(&bincode_dir).unwrap();
+    let point = Point { x: 1, y: -2 };
+
+    let mut json: DirStorage<Serde<Point, JsonFormat>> = DirStorage::default();
+    json.insert("p", Serde::new(point.clone()));
+    json.store(json_dir.to_str().unwrap()).unwrap();
+    assert_eq!(std::fs::read_to_string(json_dir.join("p")).unwrap(), r#"{"x":1,"y":-2}"#);
+    let restored: DirStorage<Serde<Point, JsonFormat>> = DirStorage::restore(json_dir.to_str().unwrap()).unwrap();
+    assert_eq!(restored, json);
+
+    let mut bincode: DirStorage<Serde<Point, BincodeFormat>> = DirStorage::default();
+    bincode.insert("p", Serde::new(point));
+    bincode.store(bincode_dir.to_str().unwrap()).unwrap();
+    let restored: DirStorage<Serde<Point, BincodeFormat>> =
+        DirStorage::restore(bincode_dir.to_str().unwrap()).unwrap();
+    assert_eq!(restored, bincode);
+    assert_eq!(restored.get("p").unwrap().y, -2);
+
+    assert!(DirStorage::<Serde<Point, JsonFormat>>::restore(bincode_dir.to_str().unwrap()).is_err());
+}
+
+#[test]
+fn serde_format_at_runtime() {
+    use soter::adaptors::SerdeFormat;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let mut dir_storage: DirStorage<Point> = DirStorage::default();
+    dir_storage.insert("p", Point { x: 1, y: -2 });
+    dir_storage.store_with_ctx(dir_str, &SerdeFormat::Json).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("p")).unwrap(), r#"{"x":1,"y":-2}"#);
+    let restored: DirStorage<Point> = DirStorage::restore_with_ctx(dir_str, &SerdeFormat::Json).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    dir_storage.store_with_ctx(dir_str, &SerdeFormat::Bincode).unwrap();
+    let restored: DirStorage<Point> = DirStorage::restore_with_ctx(dir_str, &SerdeFormat::Bincode).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    dir_storage.store_with_ctx(dir_str, &SerdeFormat::Json).unwrap();
+    let message = DirStorage::<Point>::restore_with_ctx(dir_str, &SerdeFormat::Bincode)
+        .unwrap_err()
+        .to_string();
+    assert!(message.ends_with("but the file looks like JSON"), "{}", message);
+}
+
+#[test]
+fn serde_format_in_options() {
+    use soter::adaptors::SerdeFormat;
+    use soter::dir::{Error, Options};
+    use std::collections::HashMap;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let json = Options::default().serde_format(SerdeFormat::Json);
+
+    let mut dir_storage: DirStorage<Point> = DirStorage::with_options(HashMap::new(), json.clone());
+    dir_storage.insert("p", Point { x: 1, y: -2 });
+    dir_storage.store_formatted(dir_str).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("p")).unwrap(), r#"{"x":1,"y":-2}"#);
+    let restored: DirStorage<Point> = DirStorage::restore_formatted(dir_str, json).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    let bincode = Options::default().serde_format(SerdeFormat::Bincode);
+    let message = DirStorage::<Point>::restore_formatted(dir_str, bincode.clone())
+        .unwrap_err()
+        .to_string();
+    assert!(message.ends_with("but the file looks like JSON"), "{}", message);
+
+    let mut dir_storage: DirStorage<Point> = DirStorage::with_options(HashMap::new(), bincode.clone());
+    dir_storage.insert("p", Point { x: 3, y: 4 });
+    dir_storage.store_single_formatted(dir_str, "p").unwrap();
+    let restored: DirStorage<Point> = DirStorage::restore_formatted(dir_str, bincode).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    match DirStorage::<Point>::default().store_formatted(dir_str) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn export_json() {
+    let mut dir_storage: DirStorage<Point> = DirStorage::default();
+    dir_storage.insert("b", Point { x: 3, y: 4 });
+    dir_storage.insert("a", Point { x: 1, y: -2 });
+
+    let mut document = Vec::new();
+    dir_storage.export_json(&mut document).unwrap();
+    assert_eq!(
+        String::from_utf8(document.clone()).unwrap(),
+        r#"{"a":{"x":1,"y":-2},"b":{"x":3,"y":4}}"#
+    );
+    let imported: DirStorage<Point> = DirStorage::import_json(document.as_slice()).unwrap();
+    assert_eq!(imported, dir_storage);
+
+    assert!(DirStorage::<Point>::import_json(&b"[1, 2]"[..]).is_err());
+}
@@ -0,0 +1,128 @@
+#![cfg(feature = "sqlite")]
+
+use tempdir::TempDir;
+
+use soter::dir::Error;
+use soter::storage::{BatchOp, SqliteStorage, Storage};
+
+#[test]
+fn sqlite_storage() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let path = dir.path().join("items.db");
+
+    let mut storage = SqliteStorage::open(&path).unwrap();
+    Storage::<u32>::save(&mut storage, "a", &1).unwrap();
+    Storage::<u32>::save(&mut storage, "a", &2).unwrap();
+    Storage::<u32>::save(&mut storage, "b", &3).unwrap();
+    assert!(Storage::<u32>::delete(&mut storage, "b").unwrap());
+    assert!(!Storage::<u32>::delete(&mut storage, "b").unwrap());
+    drop(storage);
+
+    let mut storage = SqliteStorage::open(&path).unwrap();
+    assert_eq!(Storage::<u32>::load(&mut storage, "a").unwrap(), Some(2));
+    assert_eq!(Storage::<u32>::load(&mut storage, "b").unwrap(), None);
+    assert_eq!(Storage::<u32>::keys(&mut storage).unwrap(), vec!["a".to_string()]);
+}
+
+#[test]
+fn sqlite_transaction() {
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+    let result: Result<(), Error> = storage.transaction(|storage| {
+        Storage::<u32>::save(storage, "a", &1)?;
+        Err(Error::NotFound("b".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(Storage::<u32>::load(&mut storage, "a").unwrap(), None);
+
+    storage
+        .transaction(|storage| {
+            Storage::<u32>::save(storage, "a", &1)?;
+            Storage::<u32>::save(storage, "b", &2)
+        })
+        .unwrap();
+    let mut keys = Storage::<u32>::keys(&mut storage).unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["a".to_string(), "b".to_string()]);
+}
+
+#[test]
+fn sqlite_nested_transaction() {
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+    storage
+        .transaction(|storage| {
+            Storage::<u32>::save(storage, "a", &1)?;
+            let inner: Result<(), Error> = storage.transaction(|storage| {
+                Storage::<u32>::save(storage, "b", &2)?;
+                Err(Error::NotFound("b".to_string()))
+            });
+            assert!(inner.is_err());
+            storage.batch(vec![BatchOp::Put("c".to_string(), 3u32)])
+        })
+        .unwrap();
+    let mut keys = Storage::<u32>::keys(&mut storage).unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["a".to_string(), "c".to_string()]);
+
+    let result: Result<(), Error> = storage.transaction(|storage| {
+        storage.transaction(|storage| Storage::<u32>::save(storage, "d", &4))?;
+        Err(Error::NotFound("d".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(Storage::<u32>::load(&mut storage, "d").unwrap(), None);
+
+    // The error of the transaction is kept when rolling back fails.
+    let result: Result<(), Error> = storage.transaction(|storage| {
+        storage.connection().execute_batch("RELEASE soter_transaction").unwrap();
+        Err(Error::NotFound("e".to_string()))
+    });
+    assert!(matches!(result, Err(Error::NotFound(_))));
+}
+
+#[test]
+fn sqlite_batch() {
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+    assert!(Storage::<u32>::capabilities(&storage).atomic_batch);
+    storage
+        .batch(vec![
+            BatchOp::Put("a".to_string(), 1u32),
+            BatchOp::Put("b".to_string(), 2),
+            BatchOp::Delete("a".to_string()),
+        ])
+        .unwrap();
+    assert_eq!(Storage::<u32>::keys(&mut storage).unwrap(), vec!["b".to_string()]);
+
+    storage
+        .connection()
+        .execute_batch("CREATE TRIGGER no_c BEFORE INSERT ON items WHEN NEW.key = 'c' BEGIN SELECT RAISE(ABORT, 'no c'); END")
+        .unwrap();
+    let ops = vec![BatchOp::Put("d".to_string(), 4u32), BatchOp::Put("c".to_string(), 3)];
+    assert!(storage.batch(ops).is_err());
+    assert_eq!(Storage::<u32>::load(&mut storage, "d").unwrap(), None);
+}
+
+#[test]
+fn sqlite_snapshot() {
+    use soter::storage::SnapshotHandle;
+
+    let dir = TempDir::new("soter_test").unwrap();
+    let path = dir.path().join("items.db");
+
+    let mut storage = SqliteStorage::open(&path).unwrap();
+    Storage::<u32>::save(&mut storage, "a", &1).unwrap();
+    let snapshot = Storage::<u32>::snapshot(&storage).unwrap();
+    assert_eq!(snapshot.path().parent(), Some(dir.path()));
+
+    Storage::<u32>::save(&mut storage, "a", &2).unwrap();
+    Storage::<u32>::save(&mut storage, "b", &3).unwrap();
+    Storage::<u32>::restore_snapshot(&mut storage, &snapshot).unwrap();
+    assert_eq!(Storage::<u32>::load(&mut storage, "a").unwrap(), Some(1));
+    assert_eq!(Storage::<u32>::keys(&mut storage).unwrap(), vec!["a".to_string()]);
+
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+    Storage::<u32>::save(&mut storage, "c", &4).unwrap();
+    let snapshot = Storage::<u32>::snapshot(&storage).unwrap();
+    Storage::<u32>::delete(&mut storage, "c").unwrap();
+    Storage::<u32>::restore_snapshot(&mut storage, &SnapshotHandle::new(snapshot.path())).unwrap();
+    assert_eq!(Storage::<u32>::load(&mut storage, "c").unwrap(), Some(4));
+    std::fs::remove_file(snapshot.path()).unwrap();
+}
@@ -0,0 +1,299 @@
+use tempdir::TempDir;
+
+use soter::dir::{DirBackend, DirStorage};
+use soter::storage::*;
+
+#[test]
+fn cache_write_through() {
+    let dir = TempDir::new("soter_test").unwrap();
+
+    let mut cache = Cache::new(DirBackend::new(dir.path()), WriteMode::WriteThrough);
+    cache.insert("1", 1u32).unwrap();
+    assert_eq!(cache.get("1").unwrap(), Some(&1));
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir.path().to_str().unwrap()).unwrap();
+    assert_eq!(*restored.get("1").unwrap(), 1);
+
+    cache.remove("1").unwrap();
+    assert!(!dir.path().join("1").exists());
+}
+
+#[test]
+fn cache_write_back() {
+    let mut backend = MemStorage::default();
+    backend.save("old", &0u32).unwrap();
+
+    let mut cache = Cache::new(backend, WriteMode::WriteBack);
+    assert_eq!(cache.get("old").unwrap(), Some(&0));
+    cache.insert("new", 1).unwrap();
+    cache.remove("old").unwrap();
+    assert_eq!(cache.get("old").unwrap(), None);
+
+    let mut keys = cache.keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["new".to_string()]);
+
+    let mut backend = cache.backend().clone();
+    assert_eq!(backend.load("old").unwrap(), Some(0));
+    assert_eq!(backend.load("new").unwrap(), None);
+
+    let mut dirty: Vec<&String> = cache.dirty_keys().collect();
+    dirty.sort();
+    assert_eq!(dirty, vec!["new", "old"]);
+    assert!(cache.is_dirty("new"));
+    cache.flush().unwrap();
+    assert_eq!(cache.dirty_keys().count(), 0);
+    assert!(!cache.is_dirty("new"));
+    let mut backend = cache.backend().clone();
+    assert_eq!(backend.load("old").unwrap(), None);
+    assert_eq!(backend.load("new").unwrap(), Some(1));
+    cache.close().unwrap();
+}
+
+#[test]
+fn cache_forwards_to_backend() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let mut backend = DirBackend::new(dir.path());
+    backend.save("a", &1u32).unwrap();
+
+    let mut cache = Cache::new(backend, WriteMode::WriteBack);
+    assert!(Storage::<u32>::capabilities(&cache).atomic_batch);
+    assert_eq!(cache.get("a").unwrap(), Some(&1));
+    cache.insert("b", 2).unwrap();
+
+    // The batch replaces what is in memory and the pending insert of "b".
+    cache
+        .batch(vec![BatchOp::Put("a".to_string(), 10), BatchOp::Delete("b".to_string())])
+        .unwrap();
+    assert_eq!(cache.get("a").unwrap(), Some(&10));
+    assert_eq!(cache.get("b").unwrap(), None);
+    assert!(!cache.is_dirty("b"));
+    assert!(!dir.path().join("b").exists());
+
+    cache.insert("c", 3).unwrap();
+    cache.flush().unwrap();
+    let snapshot = Storage::<u32>::snapshot(&cache).unwrap();
+    cache.insert("a", 20).unwrap();
+    cache.remove("c").unwrap();
+    cache.flush().unwrap();
+    cache.insert("d", 4).unwrap();
+    Storage::<u32>::restore_snapshot(&mut cache, &snapshot).unwrap();
+    assert_eq!(cache.get("a").unwrap(), Some(&10));
+    assert_eq!(cache.get("c").unwrap(), Some(&3));
+    assert_eq!(cache.get("d").unwrap(), None);
+    assert_eq!(cache.dirty_keys().count(), 0);
+}
+
+#[test]
+fn tiered_read_through() {
+    let mut origin = MemStorage::default();
+    origin.save("remote", &1u32).unwrap();
+
+    let mut tiered = Tiered::new(MemStorage::default(), origin, TierWrites::PrimaryOnly);
+    assert_eq!(tiered.load("remote").unwrap(), Some(1));
+    assert_eq!(tiered.primary().clone().load("remote").unwrap(), Some(1));
+    assert_eq!(tiered.load("missing").unwrap(), None);
+
+    tiered.save("local", &2).unwrap();
+    assert_eq!(tiered.secondary().clone().load("local").unwrap(), None);
+
+    // The item read through is deleted from the secondary tier too, so it stays deleted.
+    assert!(tiered.delete("remote").unwrap());
+    assert_eq!(tiered.load("remote").unwrap(), None);
+    assert!(!tiered.delete("remote").unwrap());
+
+    let mut tiered = Tiered::new(MemStorage::default(), MemStorage::default(), TierWrites::Both);
+    tiered.save("both", &3u32).unwrap();
+    assert_eq!(tiered.secondary().clone().load("both").unwrap(), Some(3));
+    assert!(tiered.delete("both").unwrap());
+    assert_eq!(tiered.keys().unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn dir_backend_batch() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let mut backend = DirBackend::new(dir.path());
+    assert!(Storage::<u32>::capabilities(&backend).atomic_batch);
+    backend.save("old", &0u32).unwrap();
+
+    backend
+        .batch(vec![
+            BatchOp::Put("a".to_string(), 1u32),
+            BatchOp::Put("b".to_string(), 2),
+            BatchOp::Put("c".to_string(), 5),
+            BatchOp::Delete("old".to_string()),
+            BatchOp::Delete("b".to_string()),
+            BatchOp::Put("c".to_string(), 3),
+        ])
+        .unwrap();
+    let mut keys = Storage::<u32>::keys(&mut backend).unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["a".to_string(), "c".to_string()]);
+    let mut reopened = DirBackend::new(dir.path());
+    assert_eq!(reopened.load("a").unwrap(), Some(1u32));
+    assert_eq!(reopened.load("b").unwrap(), None::<u32>);
+    assert_eq!(reopened.load("c").unwrap(), Some(3u32));
+    assert_eq!(reopened.load("old").unwrap(), None::<u32>);
+
+    std::fs::create_dir(dir.path().join("d")).unwrap();
+    let ops = vec![BatchOp::Put("a".to_string(), 4u32), BatchOp::Put("d".to_string(), 4)];
+    assert!(backend.batch(ops).is_err());
+    assert_eq!(backend.load("a").unwrap(), Some(1));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 4);
+}
+
+#[test]
+fn mem_storage_batch() {
+    let mut storage = MemStorage::default();
+    assert!(Storage::<u32>::capabilities(&storage).atomic_batch);
+    storage
+        .batch(vec![BatchOp::Put("a".to_string(), 1u32), BatchOp::Delete("a".to_string())])
+        .unwrap();
+    assert_eq!(storage.keys().unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn cache_lengths() {
+    let mut backend = MemStorage::default();
+    backend.save("a", &1u32).unwrap();
+    backend.save("b", &2).unwrap();
+
+    let mut cache = Cache::new(backend, WriteMode::WriteBack);
+    assert_eq!(cache.loaded_len(), 0);
+    assert_eq!(cache.total_len().unwrap(), 2);
+
+    cache.get("a").unwrap();
+    cache.insert("c", 3).unwrap();
+    cache.remove("b").unwrap();
+    assert_eq!(cache.loaded_len(), 2);
+    assert_eq!(cache.total_len().unwrap(), 2);
+    let mut loaded: Vec<(&String, &u32)> = cache.loaded().collect();
+    loaded.sort();
+    assert_eq!(loaded, vec![(&"a".to_string(), &1), (&"c".to_string(), &3)]);
+}
+
+#[test]
+fn versioned() {
+    let dir = TempDir::new("soter_test").unwrap();
+
+    let mut versioned = Versioned::new(DirBackend::new(dir.path()), 2);
+    for v in 1..=4u32 {
+        versioned.save("a", &v).unwrap();
+    }
+    assert_eq!(versioned.load("a").unwrap(), Some(4));
+    assert_eq!(versioned.history("a").unwrap(), vec![1, 2]);
+    assert_eq!(versioned.restore_version("a", 1).unwrap(), Some(3));
+    assert_eq!(versioned.restore_version("a", 2).unwrap(), Some(2));
+    assert_eq!(versioned.restore_version("a", 3).unwrap(), None);
+    assert!(dir.path().join(".a.v2").is_file());
+    assert!(!dir.path().join(".a.v3").exists());
+
+    let restored: DirStorage<u32> = DirStorage::restore(dir.path().to_str().unwrap()).unwrap();
+    assert_eq!(restored.loaded_len(), 1);
+    assert_eq!(Storage::<u32>::keys(&mut versioned).unwrap(), vec!["a".to_string()]);
+
+    assert!(versioned.delete("a").unwrap());
+    assert_eq!(versioned.load("a").unwrap(), None);
+    assert_eq!(versioned.restore_version("a", 1).unwrap(), Some(4));
+    assert_eq!(versioned.restore_version("a", 2).unwrap(), Some(3));
+
+    let mut versioned = Versioned::new(MemStorage::default(), 3);
+    versioned.save("b", &1u32).unwrap();
+    versioned.save("b", &2).unwrap();
+    assert_eq!(versioned.history("b").unwrap(), vec![1]);
+    assert_eq!(Storage::<u32>::keys(&mut versioned).unwrap(), vec!["b".to_string()]);
+
+    let mut backend = versioned.backend().clone();
+    backend.save(".b.v2", &0).unwrap();
+    backend.save(".b.v3", &0).unwrap();
+    let mut versioned = Versioned::new(backend, 1);
+    versioned.save("b", &3).unwrap();
+    assert_eq!(versioned.restore_version("b", 1).unwrap(), Some(2));
+    assert_eq!(versioned.backend().clone().load(".b.v2").unwrap(), None);
+    assert_eq!(versioned.backend().clone().load(".b.v3").unwrap(), None);
+}
+
+#[test]
+fn versioned_history_decodes_nothing() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let mut versioned = Versioned::new(DirBackend::new(dir.path()), 3);
+    for v in 1..=3u32 {
+        versioned.save("a", &v).unwrap();
+    }
+
+    // A version that cannot be decoded is still listed, and only fails once restored.
+    std::fs::write(dir.path().join(".a.v2"), "not a number").unwrap();
+    assert_eq!(versioned.history("a").unwrap(), vec![1, 2]);
+    assert_eq!(versioned.restore_version("a", 1).unwrap(), Some(2));
+    assert!(versioned.restore_version("a", 2).is_err());
+}
+
+#[test]
+#[should_panic]
+fn versioned_without_retention() {
+    let _ = Versioned::<_, u32>::new(MemStorage::default(), 0);
+}
+
+#[test]
+fn dir_backend_snapshot() {
+    use soter::dir::{Error, Framed, Options};
+
+    for atomic_writes in [false, true] {
+        let dir = TempDir::new("soter_test").unwrap();
+        let mut backend = DirBackend::with_options(dir.path(), Options::default().atomic_writes(atomic_writes));
+        backend.save("a", &1u32).unwrap();
+        backend.save("b", &2u32).unwrap();
+        let snapshot = Storage::<u32>::snapshot(&backend).unwrap();
+        assert!(snapshot.path().starts_with(dir.path().join(".snapshots")));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(std::fs::metadata(dir.path().join("a")).unwrap().nlink(), 1);
+        }
+
+        backend.save("a", &10u32).unwrap();
+        Storage::<u32>::delete(&mut backend, "b").unwrap();
+        backend.save("c", &3u32).unwrap();
+        Storage::<u32>::restore_snapshot(&mut backend, &snapshot).unwrap();
+
+        let restored: DirStorage<u32> = DirStorage::restore(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(restored.loaded_len(), 2);
+        assert_eq!(restored.get("a"), Some(&1));
+        assert_eq!(restored.get("b"), Some(&2));
+
+        backend.save("a", &20u32).unwrap();
+        Storage::<u32>::restore_snapshot(&mut backend, &snapshot).unwrap();
+        assert_eq!(backend.load("a").unwrap(), Some(1u32));
+
+        // Appending in place changes neither the snapshot nor what it restores.
+        let framed: Framed<u32> = Framed::new(dir.path().join("f"));
+        framed.append(&1).unwrap();
+        let snapshot = Storage::<u32>::snapshot(&backend).unwrap();
+        framed.append(&2).unwrap();
+        let frames: Vec<u32> = Framed::new(snapshot.path().join("f")).iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(frames, vec![1]);
+        Storage::<u32>::restore_snapshot(&mut backend, &snapshot).unwrap();
+        framed.append(&3).unwrap();
+        let frames: Vec<u32> = Framed::new(snapshot.path().join("f")).iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(frames, vec![1]);
+        assert_eq!(framed.iter().unwrap().map(Result::unwrap).collect::<Vec<u32>>(), vec![1, 3]);
+    }
+
+    // The manifest is only ever replaced, so it is linked rather than copied.
+    #[cfg(unix)]
+    {
+        use soter::dir::NameHasher;
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = TempDir::new("soter_test").unwrap();
+        let mut backend = DirBackend::with_options(dir.path(), Options::default().name_hasher(NameHasher::default()));
+        backend.save("a", &1u32).unwrap();
+        Storage::<u32>::snapshot(&backend).unwrap();
+        assert_eq!(std::fs::metadata(dir.path().join(".soter_manifest")).unwrap().nlink(), 2);
+    }
+
+    let mut storage = MemStorage::<u32>::default();
+    assert!(matches!(storage.snapshot(), Err(Error::Unsupported(_))));
+    let handle = SnapshotHandle::new("elsewhere");
+    assert!(matches!(storage.restore_snapshot(&handle), Err(Error::Unsupported(_))));
+}
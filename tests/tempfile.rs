@@ -0,0 +1,32 @@
+#![cfg(feature = "tempfile")]
+
+use std::panic;
+
+use soter::dir::DirStorage;
+
+#[test]
+fn temporary() {
+    let (mut dir_storage, guard) = DirStorage::<u32>::temporary().unwrap();
+    let dir_path = guard.path().to_path_buf();
+    assert!(dir_path.is_dir());
+
+    dir_storage.insert("a".to_string(), 1);
+    dir_storage.store(guard.path_str()).unwrap();
+    let restored: DirStorage<u32> = DirStorage::restore(guard.path_str()).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    drop(guard);
+    assert!(!dir_path.exists());
+}
+
+#[test]
+fn temporary_removed_on_panic() {
+    let result = panic::catch_unwind(|| {
+        let (dir_storage, guard) = DirStorage::<u32>::temporary().unwrap();
+        dir_storage.store(guard.path_str()).unwrap();
+        panic!("{}", guard.path().display());
+    });
+    let payload = result.unwrap_err();
+    let dir_path = payload.downcast_ref::<String>().unwrap();
+    assert!(!std::path::Path::new(dir_path).exists());
+}
@@ -0,0 +1,56 @@
+#![cfg(feature = "zstd")]
+
+use tempdir::TempDir;
+
+use soter::adaptors::{Zstd, ZstdDictionary};
+use soter::dir::{DirStorage, Error};
+
+fn record(i: u32) -> String {
+    format!("{{\"id\": {}, \"name\": \"user-{}\", \"active\": true, \"role\": \"member\"}}", i, i)
+}
+
+#[test]
+fn zstd_dictionary() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+
+    let samples: Vec<String> = (0..500).map(record).collect();
+    let dictionary = ZstdDictionary::train(&samples, 4096).unwrap();
+    dictionary.save(dir_str).unwrap();
+
+    let mut dir_storage: DirStorage<Zstd<String>> = DirStorage::default();
+    dir_storage.insert("a", Zstd(record(1000)));
+    dir_storage.store_with_ctx(dir_str, &dictionary).unwrap();
+    let len = std::fs::metadata(dir.path().join("a")).unwrap().len();
+    assert!(len < record(1000).len() as u64);
+
+    let loaded = ZstdDictionary::load(dir_str).unwrap();
+    assert_eq!(loaded, dictionary);
+    let restored: DirStorage<Zstd<String>> = DirStorage::restore_with_ctx(dir_str, &loaded).unwrap();
+    assert_eq!(restored, dir_storage);
+
+    let other = ZstdDictionary::new(b"some other dictionary".to_vec());
+    match DirStorage::<Zstd<String>>::restore_with_ctx(dir_str, &other) {
+        Err(Error::RestoreError(_, message)) => assert_eq!(message, "compressed with another dictionary"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn zstd_dictionary_save_replaces_file() {
+    let dir = TempDir::new("soter_test").unwrap();
+    let dir_str = dir.path().to_str().unwrap();
+    let old = ZstdDictionary::new(b"old dictionary".to_vec());
+    old.save(dir_str).unwrap();
+    let saved = dir.path().join(".soter_zstd_dictionary");
+    std::fs::hard_link(&saved, dir.path().join("link")).unwrap();
+
+    // The new dictionary goes in a new file, so the old one is never written over.
+    let new = ZstdDictionary::new(b"new dictionary".to_vec());
+    new.save(dir_str).unwrap();
+    assert_eq!(ZstdDictionary::load(dir_str).unwrap(), new);
+    assert_eq!(std::fs::read(dir.path().join("link")).unwrap(), old.as_bytes());
+    let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names.len(), 2);
+}